@@ -1,5 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 extern crate gomoku;
+use gomoku::board::Board;
+use gomoku::minimax::MiniMax;
+use gomoku::monte::MonteCarlo;
 use gomoku::*;
 use std::fs;
 
@@ -8,10 +11,32 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("gomoku-solve");
     group.significance_level(0.1).sample_size(10);
     group.bench_function("monte-solve", |b| {
-        b.iter(|| algo::gomoku_solve(content.as_str(), "monte_carlo", 15, 15))
+        b.iter(|| algo::gomoku_solve(content.as_str(), "monte_carlo", 15, 15).unwrap())
     });
     group.bench_function("minimax-solve", |b| {
-        b.iter(|| algo::gomoku_solve(content.as_str(), "minimax", 15, 15))
+        b.iter(|| algo::gomoku_solve(content.as_str(), "minimax", 15, 15).unwrap())
+    });
+    let board = Board::new(content.clone(), 15, 15);
+    let player = board.next_player();
+    group.bench_function("minimax-solve-parallel", |b| {
+        b.iter(|| {
+            let mut runner = MiniMax::new_with_threads(player, 5, num_cpus::get());
+            runner.run_heuristic(&mut board.clone(), player)
+        })
+    });
+    let mut runner = MiniMax::new_with_threads(player, 3, 4);
+    group.bench_function("minimax-search-4-threads", |b| {
+        b.iter(|| {
+            runner.clear_transposition_table();
+            runner.search(&mut board.clone(), player)
+        })
+    });
+    group.bench_function("monte-solve-parallel", |b| {
+        b.iter(|| {
+            let mut monte = MonteCarlo::new(board.clone(), player, 3000);
+            monte.set_threads(num_cpus::get());
+            monte.search_move()
+        })
     });
     group.finish();
 }
@@ -1,16 +1,483 @@
+#![allow(dead_code)]
+use super::baseline::{Baseline, BaselineEngine};
 use super::board::*;
+use super::book;
+use super::difficulty::{Difficulty, LeveledEngine};
+use super::hybrid::HybridEngine;
 use super::minimax::*;
 use super::monte::*;
-use core::panic;
+use super::piskvork::{ExternalEngine, EXTERNAL_PREFIX};
+use super::rng::random_seed;
+use super::search::{SearchControl, SearchLimits, SearchResult, SearchStats, StopToken};
+use super::solver::EndgameEngine;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
 
-pub trait GomokuSolver {
-    fn best_move(input: &str, width: usize, height: usize) -> Move;
+/// A search engine playing one game after another.
+/// The engines are used through `Box<dyn Engine>`, see `create_engine`.
+pub trait Engine: Send {
+    /// Name the engine is registered under
+    fn name(&self) -> &str;
+
+    /// Forget everything kept from the previous game, e.g. trees or transposition tables
+    fn new_game(&mut self);
+
+    /// Position searched by the next `search`, the player to move follows from the stones
+    fn set_position(&mut self, board: &Board);
+
+    /// Stop token and progress callback used by the next searches
+    fn set_control(&mut self, control: SearchControl);
+
+    /// Token stopping the running search, it can be handed to another thread before searching
+    fn stop_token(&self) -> StopToken;
+
+    /// Search the position until a limit is reached or the search is stopped
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult;
+
+    /// Ask the running search to return its best move so far
+    fn stop(&self) {
+        self.stop_token().stop();
+    }
+
+    /// The opponent's most likely answer to the move of the last search, used to
+    /// ponder on it. `None` when the engine has no guess.
+    fn predicted_reply(&self) -> Option<Move> {
+        None
+    }
+}
+
+// Largest limits a request from the network or the browser may ask for, see `EngineOptions::capped`
+const MAX_REQUEST_DEPTH: i32 = 10;
+const MAX_REQUEST_NODES: u64 = 200_000;
+const MAX_REQUEST_TIME_MS: u64 = 5000;
+
+/// Options of an engine and of its searches, accepted by every front end:
+/// the library, the command line, the `/api/move` query and the wasm build.
+/// Unset values fall back to the defaults of the engine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EngineOptions {
+    /// Search threads, `GOMOKU_THREADS` when unset
+    pub threads: Option<usize>,
+    /// Seed of the random numbers, a fresh one when unset
+    pub seed: Option<u64>,
+    /// Deepest MiniMax iteration
+    pub depth: Option<i32>,
+    /// Simulations for MonteCarlo, positions searched for MiniMax
+    pub nodes: Option<u64>,
+    /// Thinking time per move in milliseconds
+    pub time_ms: Option<u64>,
+    /// Playing strength from 1 to 10, full strength when unset, see `difficulty::Difficulty`
+    pub level: Option<u8>,
 }
 
-pub fn gomoku_solve(input: &str, algo_type: &str, width: usize, height: usize) -> Move {
-    match algo_type {
-        "minimax" => MiniMax::best_move(input, width, height),
-        "monte_carlo" => MonteCarlo::best_move(input, width, height),
-        _ => panic!("invalid algo type"),
+impl EngineOptions {
+    /// Limits of the searches run with these options
+    pub fn limits(&self) -> SearchLimits {
+        SearchLimits {
+            time_ms: self.time_ms,
+            nodes: self.nodes,
+            depth: self.depth,
+        }
+    }
+
+    /// Threads the engines search with
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(search_threads)
+    }
+
+    /// These options, with the ones of `fallback` for the unset fields
+    pub fn or(&self, fallback: &EngineOptions) -> Self {
+        Self {
+            threads: self.threads.or(fallback.threads),
+            seed: self.seed.or(fallback.seed),
+            depth: self.depth.or(fallback.depth),
+            nodes: self.nodes.or(fallback.nodes),
+            time_ms: self.time_ms.or(fallback.time_ms),
+            level: self.level.or(fallback.level),
+        }
+    }
+
+    /// The options with the values of an untrusted request kept in bounds, so one
+    /// request cannot take over the machine: at most one thread per cpu, depth 10,
+    /// 200000 nodes and 5 seconds
+    pub fn capped(&self) -> Self {
+        Self {
+            threads: self.threads.map(|t| t.clamp(1, num_cpus::get())),
+            depth: self.depth.map(|d| d.clamp(1, MAX_REQUEST_DEPTH)),
+            nodes: self.nodes.map(|n| n.min(MAX_REQUEST_NODES)),
+            time_ms: self.time_ms.map(|t| t.min(MAX_REQUEST_TIME_MS)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Name and description of a registered engine, as returned by `list_engines`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineInfo {
+    pub name: String,
+    pub description: String,
+}
+
+pub type EngineConstructor = Arc<dyn Fn(&EngineOptions) -> Box<dyn Engine> + Send + Sync>;
+
+/// Returned for an engine name which is not registered
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownEngine(pub String);
+
+impl fmt::Display for UnknownEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<String> = list_engines().into_iter().map(|e| e.name).collect();
+        write!(
+            f,
+            "unknown engine: {}, available: {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownEngine {}
+
+/// Engine constructors by name
+#[derive(Clone, Default)]
+pub struct EngineRegistry {
+    engines: Vec<(EngineInfo, EngineConstructor)>,
+}
+
+impl EngineRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The engines of this crate
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "minimax",
+            "Alpha-beta search with iterative deepening and a transposition table",
+            |options| Box::new(MiniMaxEngine::new(options)),
+        );
+        let monte = [
+            ("monte_carlo", "Monte Carlo tree search with UCT selection"),
+            (
+                "monte_carlo_rave",
+                "Monte Carlo tree search with RAVE selection",
+            ),
+            (
+                "monte_carlo_puct",
+                "Monte Carlo tree search with PUCT selection and heuristic priors",
+            ),
+        ];
+        for (name, description) in monte {
+            let config = MonteCarloConfig::for_algo(name).unwrap();
+            registry.register(name, description, move |options| {
+                Box::new(MonteCarloEngine::new(name, config, options))
+            });
+        }
+        registry.register(
+            "hybrid",
+            "Immediate wins, blocks and forced wins by fours, then monte_carlo",
+            |options| {
+                let config = MonteCarloConfig::default();
+                let main = MonteCarloEngine::new("monte_carlo", config, options);
+                Box::new(HybridEngine::new("hybrid", Box::new(main)))
+            },
+        );
+        registry.register(
+            "hybrid_minimax",
+            "Immediate wins, blocks and forced wins by fours, then minimax",
+            |options| {
+                let main = MiniMaxEngine::new(options);
+                Box::new(HybridEngine::new("hybrid_minimax", Box::new(main)))
+            },
+        );
+        let baselines = [
+            (
+                Baseline::Random,
+                "Baseline: a random cell next to the stones",
+            ),
+            (
+                Baseline::Greedy,
+                "Baseline: the cell making the best own line",
+            ),
+            (
+                Baseline::OnePly,
+                "Baseline: the best candidate after wins and blocks, without searching",
+            ),
+        ];
+        for (kind, description) in baselines {
+            registry.register(kind.name(), description, move |options| {
+                Box::new(BaselineEngine::new(kind, options))
+            });
+        }
+        registry.register(
+            "endgame",
+            "Perfect play by solving the game, boards of at most 64 cells only",
+            |_| Box::new(EndgameEngine::new()),
+        );
+        registry
+    }
+
+    /// Add an engine, an engine registered before under the same name is replaced
+    pub fn register<F>(&mut self, name: &str, description: &str, constructor: F)
+    where
+        F: Fn(&EngineOptions) -> Box<dyn Engine> + Send + Sync + 'static,
+    {
+        self.engines.retain(|(info, _)| info.name != name);
+        let info = EngineInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+        };
+        self.engines.push((info, Arc::new(constructor)));
+    }
+
+    /// The engine plays at `options.level` when it is set
+    pub fn create(
+        &self,
+        name: &str,
+        options: &EngineOptions,
+    ) -> Result<Box<dyn Engine>, UnknownEngine> {
+        match self.engines.iter().find(|(info, _)| info.name == name) {
+            Some((_, constructor)) => Ok(with_level(constructor(options), options)),
+            None => Err(UnknownEngine(name.to_string())),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.engines.iter().any(|(info, _)| info.name == name)
+    }
+
+    pub fn list(&self) -> Vec<EngineInfo> {
+        self.engines.iter().map(|(info, _)| info.clone()).collect()
+    }
+}
+
+// The engine at `options.level` when it is set
+fn with_level(engine: Box<dyn Engine>, options: &EngineOptions) -> Box<dyn Engine> {
+    match options.level {
+        Some(level) => Box::new(LeveledEngine::new(
+            engine,
+            Difficulty::new(level),
+            options.seed,
+        )),
+        None => engine,
+    }
+}
+
+/// Create one of the builtin engines
+pub fn create_engine(
+    name: &str,
+    options: &EngineOptions,
+) -> Result<Box<dyn Engine>, UnknownEngine> {
+    EngineRegistry::builtin().create(name, options)
+}
+
+/// Like `create_engine`, names starting with `pbrain:` also run the external brain
+/// given after the colon. This runs any command, so it is only for front ends
+/// whose user may run programs anyway, like the command line and self-play,
+/// never for names coming from the network.
+pub fn create_engine_with_external(
+    name: &str,
+    options: &EngineOptions,
+) -> Result<Box<dyn Engine>, UnknownEngine> {
+    match name.strip_prefix(EXTERNAL_PREFIX) {
+        Some(command) if !command.trim().is_empty() => Ok(with_level(
+            Box::new(ExternalEngine::new(name, command)),
+            options,
+        )),
+        _ => create_engine(name, options),
+    }
+}
+
+/// Whether `name` asks for an external brain, which network front ends refuse
+pub fn is_external(name: &str) -> bool {
+    name.trim_start().starts_with(EXTERNAL_PREFIX)
+}
+
+/// The builtin engines, in the order they are offered to players
+pub fn list_engines() -> Vec<EngineInfo> {
+    EngineRegistry::builtin().list()
+}
+
+pub fn gomoku_solve(
+    input: &str,
+    algo_type: &str,
+    width: usize,
+    height: usize,
+) -> Result<Move, UnknownEngine> {
+    let options = EngineOptions::default();
+    gomoku_solve_with(
+        input,
+        algo_type,
+        width,
+        height,
+        &options,
+        &SearchControl::default(),
+    )
+    .map(|res| res.best_move)
+}
+
+/// Same as `gomoku_solve`, with the engine and its search set up by `options`.
+/// The search can be stopped and reports its progress through `control`.
+/// Positions found in the opening book are answered from the book without
+/// creating an engine. With a `seed` the same position always gets the same move.
+/// Every call starts a new engine, front ends playing a whole game keep one
+/// `Engine` instead, like `self_play::SelfPlay` does.
+pub fn gomoku_solve_with(
+    input: &str,
+    algo_type: &str,
+    width: usize,
+    height: usize,
+    options: &EngineOptions,
+    control: &SearchControl,
+) -> Result<SearchResult, UnknownEngine> {
+    let options = EngineOptions {
+        seed: Some(options.seed.unwrap_or_else(random_seed)),
+        ..options.clone()
+    };
+    let registry = EngineRegistry::builtin();
+    if !registry.contains(algo_type) {
+        return Err(UnknownEngine(algo_type.to_string()));
+    }
+    let board = Board::new(input.to_string(), width, height);
+    if let Some(mv) = book::global_lookup(&board) {
+        return Ok(SearchResult {
+            best_move: mv,
+            stats: SearchStats::default(),
+            limits: options.limits(),
+        });
+    }
+    let mut engine = registry.create(algo_type, &options)?;
+    engine.set_position(&board);
+    engine.set_control(control.clone());
+    Ok(engine.search(&options.limits()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Player 1 has four in a row, open at (7, 11) only
+    fn four_board() -> Board {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        board.place(7, 10, 1);
+        board.place(7, 6, 2);
+        board.place(8, 8, 2);
+        board.place(8, 9, 2);
+        board
+    }
+
+    #[test]
+    fn test_list_engines() {
+        let names: Vec<String> = list_engines().into_iter().map(|e| e.name).collect();
+        assert!(names.contains(&"minimax".to_string()));
+        assert!(names.contains(&"monte_carlo_puct".to_string()));
+        assert!(names.contains(&"one_ply".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_engine() {
+        let err = create_engine("alphazero", &EngineOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(err, UnknownEngine("alphazero".to_string()));
+        assert!(err.to_string().contains("minimax"));
+        assert!(gomoku_solve(&"0".repeat(225), "alphazero", 15, 15).is_err());
+    }
+
+    #[test]
+    fn test_external_engines_are_not_builtin() {
+        // Only the front ends which may run programs start external brains
+        assert!(create_engine("pbrain:true", &EngineOptions::default()).is_err());
+        assert!(gomoku_solve(&"0".repeat(225), "pbrain:true", 15, 15).is_err());
+        assert!(is_external(" pbrain:true") && !is_external("minimax"));
+    }
+
+    #[test]
+    fn test_engines_block_a_four() {
+        let board = four_board();
+        for name in list_engines().into_iter().map(|e| e.name) {
+            // The random and greedy baselines do not look at the opponent,
+            // the endgame solver does not play boards this large
+            if name == "random" || name == "greedy" || name == "endgame" {
+                continue;
+            }
+            let mut engine = create_engine(&name, &EngineOptions::default()).unwrap();
+            assert_eq!(engine.name(), name);
+            engine.set_position(&board);
+            let res = engine.search(&SearchLimits::nodes(200));
+            // The four has to be blocked
+            assert_eq!((res.best_move.x, res.best_move.y), (7, 11));
+            engine.new_game();
+        }
+    }
+
+    #[test]
+    fn test_search_limits_and_stats() {
+        let board = four_board();
+        let options = EngineOptions {
+            depth: Some(2),
+            threads: Some(1),
+            ..EngineOptions::default()
+        };
+        let res = gomoku_solve_with(
+            &board.to_string(),
+            "minimax",
+            15,
+            15,
+            &options,
+            &SearchControl::default(),
+        )
+        .unwrap();
+        assert_eq!(res.limits.depth, Some(2));
+        assert!(res.stats.depth >= 1 && res.stats.depth <= 2);
+        assert!(res.stats.nodes > 0 && res.stats.evaluations > 0);
+        let res = gomoku_solve_with(
+            &board.to_string(),
+            "monte_carlo",
+            15,
+            15,
+            &EngineOptions::default(),
+            &SearchControl::default(),
+        )
+        .unwrap();
+        assert_eq!(res.limits.nodes, Some(3000));
+        assert!(res.stats.simulations > 0 && res.stats.evaluations >= res.stats.simulations);
+    }
+
+    #[test]
+    fn test_capped_options() {
+        let options = EngineOptions {
+            threads: Some(100_000),
+            depth: Some(50),
+            nodes: Some(u64::MAX),
+            time_ms: Some(3_600_000),
+            level: Some(3),
+            ..EngineOptions::default()
+        }
+        .capped();
+        assert_eq!(options.threads, Some(num_cpus::get()));
+        assert_eq!(options.depth, Some(MAX_REQUEST_DEPTH));
+        assert_eq!(options.nodes, Some(MAX_REQUEST_NODES));
+        assert_eq!(options.time_ms, Some(MAX_REQUEST_TIME_MS));
+        assert_eq!(options.level, Some(3));
+        assert_eq!(EngineOptions::default().capped(), EngineOptions::default());
+    }
+
+    #[test]
+    fn test_custom_registry() {
+        let mut registry = EngineRegistry::new();
+        registry.register("custom", "", |options| {
+            Box::new(MiniMaxEngine::new(options))
+        });
+        assert_eq!(registry.list().len(), 1);
+        assert!(registry.contains("custom") && !registry.contains("minimax"));
+        assert!(registry
+            .create("minimax", &EngineOptions::default())
+            .is_err());
     }
 }
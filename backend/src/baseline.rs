@@ -0,0 +1,182 @@
+#![allow(dead_code)]
+// Baseline engines - weak reference players for Elo calibration and self-play checks
+use super::algo::{Engine, EngineOptions};
+use super::board::*;
+use super::rng::{random_seed, GameRng};
+use super::search::{SearchControl, SearchLimits, SearchResult, SearchStats, StopToken, Timer};
+
+/// How a baseline engine picks its move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Baseline {
+    /// Any candidate cell next to the stones, uniformly
+    Random,
+    /// The cell with the highest `eval_pos` for the player to move
+    Greedy,
+    /// The best move of `gen_ordered_moves_all`, which wins or blocks first
+    OnePly,
+}
+
+impl Baseline {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Baseline::Random => "random",
+            Baseline::Greedy => "greedy",
+            Baseline::OnePly => "one_ply",
+        }
+    }
+}
+
+/* Plays without searching, the moves are chosen from the candidates of the
+ * position alone. Equally good candidates are drawn at random, so games
+ * between baselines differ, and the same seed gives the same games.
+ */
+pub struct BaselineEngine {
+    kind: Baseline,
+    seed: u64,
+    rng: GameRng,
+    board: Board,
+    control: SearchControl,
+}
+
+impl BaselineEngine {
+    pub fn new(kind: Baseline, options: &EngineOptions) -> Self {
+        let seed = options.seed.unwrap_or_else(random_seed);
+        Self {
+            kind,
+            seed,
+            rng: GameRng::new(seed),
+            board: Board::new_default(),
+            control: SearchControl::default(),
+        }
+    }
+
+    // Candidates of the position, the best ones first for the greedy kinds
+    fn candidates(&mut self, player: u8) -> Vec<Move> {
+        match self.kind {
+            Baseline::Random | Baseline::Greedy => self.board.gen_ordered_moves(player),
+            Baseline::OnePly => self.board.gen_ordered_moves_all(player),
+        }
+    }
+
+    // None when there are no candidates, e.g. on a full board
+    fn pick(&mut self, moves: &[Move]) -> Option<Move> {
+        if moves.is_empty() {
+            return None;
+        }
+        let count = match self.kind {
+            Baseline::Random => moves.len(),
+            Baseline::Greedy | Baseline::OnePly => moves
+                .iter()
+                .take_while(|m| {
+                    m.score == moves[0].score && m.original_score == moves[0].original_score
+                })
+                .count(),
+        };
+        Some(moves[self.rng.below(count)])
+    }
+}
+
+impl Engine for BaselineEngine {
+    fn name(&self) -> &str {
+        self.kind.name()
+    }
+
+    fn new_game(&mut self) {
+        self.board = Board::new_default();
+        self.rng = GameRng::new(self.seed);
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.control.stop.clone()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let timer = Timer::start();
+        let player = self.board.next_player();
+        let moves = self.candidates(player);
+        let stats = SearchStats {
+            depth: 1,
+            nodes: moves.len() as u64,
+            evaluations: moves.len() as u64,
+            elapsed_ms: timer.elapsed_ms(),
+            threads: 1,
+            ..SearchStats::default()
+        };
+        match self.pick(&moves) {
+            Some(best_move) => SearchResult {
+                best_move,
+                stats,
+                limits: *limits,
+            },
+            None => SearchResult::no_move(stats, *limits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algo::create_engine;
+
+    #[test]
+    fn test_baseline_engines() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        board.place(7, 10, 1);
+        board.place(7, 6, 2);
+        board.place(8, 8, 2);
+        board.place(8, 9, 2);
+        let limits = SearchLimits::default();
+        let options = EngineOptions {
+            seed: Some(7),
+            ..EngineOptions::default()
+        };
+
+        // Only one_ply looks at the opponent's four
+        let mut engine = create_engine("one_ply", &options).unwrap();
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        assert_eq!((res.best_move.x, res.best_move.y), (7, 11));
+
+        // greedy makes its own best line, a five once it has a four
+        let mut engine = create_engine("greedy", &options).unwrap();
+        board.place(8, 7, 2);
+        board.place(0, 0, 1);
+        board.place(8, 10, 2);
+        board.place(0, 14, 1);
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        assert!([(8, 6), (8, 11)].contains(&(res.best_move.x, res.best_move.y)));
+
+        // random plays next to the stones, the same seed gives the same moves
+        let mut first = create_engine("random", &options).unwrap();
+        let mut second = create_engine("random", &options).unwrap();
+        first.set_position(&board);
+        second.set_position(&board);
+        for _ in 0..5 {
+            let a = first.search(&limits).best_move;
+            let b = second.search(&limits).best_move;
+            assert_eq!((a.x, a.y), (b.x, b.y));
+            assert_eq!(board.get(a.x as i32, a.y as i32), Some(0));
+            assert!(!board.is_remote_cell(a.x, a.y));
+        }
+
+        // Nothing to pick on a full board
+        let full = Board::new("12".repeat(9 * 9 / 2) + "1", 9, 9);
+        for name in ["random", "greedy", "one_ply"] {
+            let mut engine = create_engine(name, &options).unwrap();
+            engine.set_position(&full);
+            assert!(!engine.search(&limits).has_move(), "{}", name);
+        }
+    }
+}
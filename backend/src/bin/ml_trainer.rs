@@ -1,6 +1,15 @@
 // ML 训练工具 - 用于生成训练数据
+use gomoku::algo::{create_engine_with_external, list_engines, EngineOptions};
+use gomoku::board::Board;
+use gomoku::book::OpeningBook;
+use gomoku::export::{ExportOptions, SearchTreeExport};
 use gomoku::game_record::DatasetManager;
+use gomoku::minimax::MiniMax;
+use gomoku::monte::{MonteCarlo, MonteCarloConfig};
+use gomoku::rng::{random_seed, GameRng};
 use gomoku::self_play::{SelfPlay, Tournament};
+use gomoku::solver::{audit_engine, sample_positions, EndgameCache, EndgameEngine};
+use gomoku::utils::{BOARD_HEIGHT, BOARD_WIDTH};
 
 use clap::{App, Arg};
 
@@ -21,19 +30,38 @@ fn main() {
                 .takes_value(true)
                 .help("锦标赛模式 (指定每对算法的游戏数量)"),
         )
+        .arg(
+            Arg::new("engines")
+                .long("engines")
+                .takes_value(true)
+                .multiple_values(true)
+                .help("参加锦标赛的算法，默认所有注册的引擎"),
+        )
         .arg(
             Arg::new("algo1")
                 .long("algo1")
                 .takes_value(true)
                 .default_value("minimax")
-                .help("算法1: minimax 或 monte_carlo"),
+                .help("算法1: 任一注册的引擎，如 minimax, monte_carlo 或 random"),
         )
         .arg(
             Arg::new("algo2")
                 .long("algo2")
                 .takes_value(true)
                 .default_value("monte_carlo")
-                .help("算法2: minimax 或 monte_carlo"),
+                .help("算法2: 任一注册的引擎，如 minimax, monte_carlo 或 random"),
+        )
+        .arg(
+            Arg::new("level1")
+                .long("level1")
+                .takes_value(true)
+                .help("算法1 的难度等级 (1-10)，默认全力"),
+        )
+        .arg(
+            Arg::new("level2")
+                .long("level2")
+                .takes_value(true)
+                .help("算法2 的难度等级 (1-10)，默认全力"),
         )
         .arg(
             Arg::new("verbose")
@@ -55,8 +83,268 @@ fn main() {
                 .takes_value(true)
                 .help("启用随机开局模式 (指定前 N 步随机，最多3步，例如 --exploration 3)"),
         )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("随机种子 (自我对弈、锦标赛和失误率检查)，同样的种子和参数会下出同样的对局"),
+        )
+        .arg(
+            Arg::new("book")
+                .long("book")
+                .takes_value(true)
+                .help("自我对弈时从开局库中选取开局 (配合 --exploration 指定步数，默认 6)"),
+        )
+        .arg(
+            Arg::new("book-build")
+                .long("book-build")
+                .takes_value(true)
+                .help("从对局数据 (JSON) 构建开局库"),
+        )
+        .arg(
+            Arg::new("book-merge")
+                .long("book-merge")
+                .takes_value(true)
+                .multiple_values(true)
+                .help("合并多个开局库文件"),
+        )
+        .arg(
+            Arg::new("book-inspect")
+                .long("book-inspect")
+                .takes_value(true)
+                .help("查看开局库的统计和开局着法"),
+        )
+        .arg(
+            Arg::new("book-out")
+                .long("book-out")
+                .takes_value(true)
+                .default_value("data/book.json")
+                .help("开局库输出文件"),
+        )
+        .arg(
+            Arg::new("book-ply")
+                .long("book-ply")
+                .takes_value(true)
+                .default_value("8")
+                .help("构建开局库时每局学习的前 N 步"),
+        )
+        .arg(
+            Arg::new("endgame-build")
+                .long("endgame-build")
+                .takes_value(true)
+                .help("穷举求解小棋盘并保存求解缓存 (指定大小，例如 5x5)"),
+        )
+        .arg(
+            Arg::new("endgame-audit")
+                .long("endgame-audit")
+                .takes_value(true)
+                .help("用求解缓存和完美对局检查 minimax 和 monte_carlo 的失误率"),
+        )
+        .arg(
+            Arg::new("win-len")
+                .long("win-len")
+                .takes_value(true)
+                .default_value("4")
+                .help("连成几子获胜 (用于 --endgame-build)"),
+        )
+        .arg(
+            Arg::new("endgame-out")
+                .long("endgame-out")
+                .takes_value(true)
+                .default_value("data/endgame.cache")
+                .help("求解缓存输出文件"),
+        )
+        .arg(
+            Arg::new("positions")
+                .long("positions")
+                .takes_value(true)
+                .default_value("100")
+                .help("检查的随机局面数量 (用于 --endgame-audit)"),
+        )
+        .arg(
+            Arg::new("export-tree")
+                .long("export-tree")
+                .takes_value(true)
+                .help("用 --algo1 搜索 --board 局面并导出搜索树 (.dot 或 .json)"),
+        )
+        .arg(
+            Arg::new("board")
+                .long("board")
+                .takes_value(true)
+                .help("15x15 局面, 225 个字符 (0 空, 1 黑, 2 白)"),
+        )
+        .arg(
+            Arg::new("export-depth")
+                .long("export-depth")
+                .takes_value(true)
+                .default_value("3")
+                .help("导出搜索树的层数"),
+        )
+        .arg(
+            Arg::new("export-min-visits")
+                .long("export-min-visits")
+                .takes_value(true)
+                .default_value("1")
+                .help("访问次数少于此值的节点不导出"),
+        )
         .get_matches();
 
+    // 导出搜索树
+    if let Some(out) = matches.value_of("export-tree") {
+        let input = matches.value_of("board").expect("请用 --board 指定局面");
+        let algo = matches.value_of("algo1").unwrap();
+        let options = ExportOptions {
+            max_depth: matches
+                .value_of("export-depth")
+                .unwrap()
+                .parse()
+                .expect("层数必须是数字"),
+            min_visits: matches
+                .value_of("export-min-visits")
+                .unwrap()
+                .parse()
+                .expect("访问次数必须是数字"),
+        };
+        let export = export_tree(input, algo, &options);
+        export.save(out).expect("保存搜索树失败");
+        println!(
+            "✅ {} 的搜索树已保存到: {} ({} 个节点, 主变化 {:?})",
+            algo,
+            out,
+            export.root.size(),
+            export.root.pv_moves()
+        );
+        return;
+    }
+
+    // 穷举求解小棋盘
+    if let Some(size) = matches.value_of("endgame-build") {
+        let (width, height) = match size.split_once('x') {
+            Some((w, h)) => (
+                w.parse::<usize>().expect("棋盘大小格式: 5x5"),
+                h.parse::<usize>().expect("棋盘大小格式: 5x5"),
+            ),
+            None => panic!("棋盘大小格式: 5x5"),
+        };
+        let win_len: usize = matches
+            .value_of("win-len")
+            .unwrap()
+            .parse()
+            .expect("连子数必须是数字");
+        let out = matches.value_of("endgame-out").unwrap();
+
+        println!(
+            "🧮 穷举求解 {}x{} 棋盘 (连 {} 子获胜)",
+            width, height, win_len
+        );
+        let start = std::time::Instant::now();
+        let (cache, outcome) = match EndgameCache::build(width, height, win_len) {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return;
+            }
+        };
+        println!("   结果 (先手): {:?}", outcome);
+        println!("   局面数: {} (精确值 {})", cache.len(), cache.exact_len());
+        println!("   用时: {:.1}s", start.elapsed().as_secs_f32());
+
+        if let Some(dir) = std::path::Path::new(out).parent() {
+            std::fs::create_dir_all(dir).ok();
+        }
+        cache.save(out).expect("保存缓存失败");
+        println!("✅ 缓存已保存到: {}", out);
+        return;
+    }
+
+    // 检查算法失误率
+    if let Some(file) = matches.value_of("endgame-audit") {
+        let count: usize = matches
+            .value_of("positions")
+            .unwrap()
+            .parse()
+            .expect("局面数量必须是数字");
+        let cache = EndgameCache::load(file).expect("读取缓存失败");
+        let mut rng = GameRng::new(parse_seed(&matches).unwrap_or_else(random_seed));
+        let setup = EndgameEngine::with_cache(&cache).and_then(|engine| {
+            let positions =
+                sample_positions(cache.width, cache.height, cache.win_len, count, &mut rng)?;
+            Ok((engine, positions))
+        });
+        let (mut engine, positions) = match setup {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return;
+            }
+        };
+
+        println!(
+            "🔍 在 {} 个 {}x{} 局面上检查算法 (连 {} 子获胜)",
+            positions.len(),
+            cache.width,
+            cache.height,
+            cache.win_len
+        );
+        for algo in ["minimax", "monte_carlo"] {
+            let report = audit_engine(&mut engine, algo, &positions).unwrap();
+            println!(
+                "   {}: {}/{} 步不是最优 ({:.1}%)",
+                algo,
+                report.suboptimal,
+                report.positions,
+                report.error_rate() * 100.0
+            );
+        }
+        return;
+    }
+
+    // 构建开局库
+    if let Some(json_file) = matches.value_of("book-build") {
+        let ply: usize = matches
+            .value_of("book-ply")
+            .unwrap()
+            .parse()
+            .expect("步数必须是数字");
+        let out = matches.value_of("book-out").unwrap();
+        let dataset = DatasetManager::load_from_file(json_file).expect("读取对局数据失败");
+
+        // 自我对弈的对局都使用默认大小的棋盘
+        let mut book = OpeningBook::new(BOARD_WIDTH, BOARD_HEIGHT, ply);
+        for game in dataset.games() {
+            book.add_game(game);
+        }
+        save_book(&book, out);
+        println!("✅ 从 {} 局对局构建开局库", dataset.games().len());
+        print_book(&book);
+        return;
+    }
+
+    // 合并开局库
+    if let Some(files) = matches.values_of("book-merge") {
+        let out = matches.value_of("book-out").unwrap();
+        let mut merged: Option<OpeningBook> = None;
+        for file in files {
+            let book = OpeningBook::load(file).expect("读取开局库失败");
+            match merged.as_mut() {
+                Some(merged) => merged.merge(&book).expect("合并开局库失败"),
+                None => merged = Some(book),
+            }
+        }
+        let merged = merged.expect("没有指定开局库文件");
+        save_book(&merged, out);
+        println!("✅ 开局库已合并");
+        print_book(&merged);
+        return;
+    }
+
+    // 查看开局库
+    if let Some(file) = matches.value_of("book-inspect") {
+        let book = OpeningBook::load(file).expect("读取开局库失败");
+        print_book(&book);
+        return;
+    }
+
     // 自我对弈模式
     if let Some(num_games) = matches.value_of("selfplay") {
         let num: usize = num_games.parse().expect("请提供有效的游戏数量");
@@ -66,11 +354,18 @@ fn main() {
         let output_prefix = matches.value_of("output").unwrap();
         let exploration = matches.value_of("exploration");
 
+        for algo in [algo1, algo2] {
+            if let Err(e) = create_engine_with_external(algo, &EngineOptions::default()) {
+                eprintln!("❌ {}", e);
+                return;
+            }
+        }
+
         println!("🎮 自我对弈模式");
         println!("   游戏数量: {}", num);
         println!("   算法: {} vs {}", algo1, algo2);
 
-        let self_play = if let Some(exp_str) = exploration {
+        let mut self_play = if let Some(exp_str) = exploration {
             let opening_steps: usize = exp_str.parse().expect("随机开局步数必须是数字");
             println!("   🎲 随机开局: 前 {} 步随机", opening_steps.min(3));
             SelfPlay::new_with_random_opening(300, verbose, opening_steps)
@@ -78,6 +373,26 @@ fn main() {
             SelfPlay::new(300, verbose)
         };
 
+        if let Some(book_file) = matches.value_of("book") {
+            let book = OpeningBook::load(book_file).expect("读取开局库失败");
+            let opening_steps: usize =
+                exploration.map_or(6, |s| s.parse().expect("开局步数必须是数字"));
+            println!("   📖 开局库: {} (最多 {} 步)", book_file, opening_steps);
+            self_play.set_opening_book(book, opening_steps);
+        }
+
+        if let Some(seed) = parse_seed(&matches) {
+            println!("   🌱 随机种子: {}", seed);
+            self_play.set_seed(seed);
+        }
+
+        let level1 = parse_level(&matches, "level1");
+        let level2 = parse_level(&matches, "level2");
+        if level1.is_some() || level2.is_some() {
+            println!("   🎚️  难度等级: {:?} vs {:?}", level1, level2);
+            self_play.set_levels(level1, level2);
+        }
+
         println!("   输出: {}.json / {}.csv\n", output_prefix, output_prefix);
 
         let records = self_play.play_multiple_games(num, algo1, algo2);
@@ -114,9 +429,22 @@ fn main() {
         let num: usize = games_per_pair.parse().expect("请提供有效的游戏数量");
         let output_prefix = matches.value_of("output").unwrap();
 
-        let algorithms = vec!["minimax".to_string(), "monte_carlo".to_string()];
+        // 默认所有注册的引擎互相对战
+        let algorithms: Vec<String> = match matches.values_of("engines") {
+            Some(engines) => engines.map(|e| e.to_string()).collect(),
+            None => list_engines().into_iter().map(|e| e.name).collect(),
+        };
+        for algo in algorithms.iter() {
+            if let Err(e) = create_engine_with_external(algo, &EngineOptions::default()) {
+                eprintln!("❌ {}", e);
+                return;
+            }
+        }
 
-        let tournament = Tournament::new(algorithms, num);
+        let mut tournament = Tournament::new(algorithms, num);
+        if let Some(seed) = parse_seed(&matches) {
+            tournament.set_seed(seed);
+        }
         let records = tournament.run();
 
         // 保存数据
@@ -152,8 +480,32 @@ fn main() {
     println!("  # 生成 100 局数据用于训练");
     println!("  cargo run --release --bin ml_trainer -- --selfplay 100 --algo1 minimax --algo2 monte_carlo");
     println!();
+    println!("  # 比较 RAVE / PUCT 和普通 UCT");
+    println!("  cargo run --release --bin ml_trainer -- --selfplay 20 --algo1 monte_carlo_rave --algo2 monte_carlo");
+    println!("  cargo run --release --bin ml_trainer -- --selfplay 20 --algo1 monte_carlo_puct --algo2 monte_carlo");
+    println!();
     println!("  # 锦标赛模式: 所有算法互相对战");
     println!("  cargo run --release --bin ml_trainer -- --tournament 5");
+    println!("  cargo run --release --bin ml_trainer -- --tournament 5 --engines minimax random greedy one_ply");
+    println!();
+    println!("  # 从对局数据构建开局库，合并并查看");
+    println!("  cargo run --release --bin ml_trainer -- --book-build data/games.json --book-out data/book.json");
+    println!(
+        "  cargo run --release --bin ml_trainer -- --book-merge data/book.json data/book2.json"
+    );
+    println!("  cargo run --release --bin ml_trainer -- --book-inspect data/book.json");
+    println!();
+    println!("  # 导出 monte_carlo 在某个局面的搜索树, 用 Graphviz 查看");
+    println!("  cargo run --release --bin ml_trainer -- --export-tree tree.dot --board <225 个字符> --algo1 monte_carlo --export-min-visits 20");
+    println!();
+    println!("  # 穷举求解 5x5 棋盘 (连4子)，并检查算法的失误率");
+    println!("  cargo run --release --bin ml_trainer -- --endgame-build 5x5 --win-len 4");
+    println!(
+        "  cargo run --release --bin ml_trainer -- --endgame-audit data/endgame.cache --positions 50"
+    );
+    println!();
+    println!("  # 使用开局库进行自我对弈");
+    println!("  cargo run --release --bin ml_trainer -- --selfplay 100 --book data/book.json");
     println!();
     println!("  # 详细模式 (显示棋盘)");
     println!("  cargo run --release --bin ml_trainer -- --selfplay 1 -v");
@@ -161,7 +513,77 @@ fn main() {
     println!("注意:");
     println!("  - 使用 --exploration 需要编译时启用 random 特性");
     println!("  - 随机开局可以增加对局多样性，避免重复的棋局");
+    println!("  - 设置环境变量 GOMOKU_BOOK=<开局库文件> 后，算法会优先使用开局库着法");
     println!();
     println!("更多帮助:");
     println!("  cargo run --bin ml_trainer -- --help");
 }
+
+fn parse_seed(matches: &clap::ArgMatches) -> Option<u64> {
+    matches
+        .value_of("seed")
+        .map(|s| s.parse().expect("随机种子必须是数字"))
+}
+
+fn parse_level(matches: &clap::ArgMatches, name: &str) -> Option<u8> {
+    matches
+        .value_of(name)
+        .map(|s| s.parse().expect("难度等级必须是 1 到 10 的数字"))
+}
+
+fn export_tree(input: &str, algo: &str, options: &ExportOptions) -> SearchTreeExport {
+    let mut board = Board::new(input.to_string(), BOARD_WIDTH, BOARD_HEIGHT);
+    let player = board.next_player();
+    if algo == "minimax" {
+        let mut runner = MiniMax::new(player, 5);
+        runner.record_tree(options.max_depth);
+        runner.search(&mut board, player);
+        return runner.export_tree(options).expect("搜索没有完成");
+    }
+    let config = MonteCarloConfig::for_algo(algo).expect("未知的算法");
+    let mut monte = MonteCarlo::new(board, player, 3000);
+    monte.set_config(config);
+    monte.search();
+    monte.export_tree(options)
+}
+
+fn save_book(book: &OpeningBook, out: &str) {
+    if let Some(dir) = std::path::Path::new(out).parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    book.save(out).expect("保存开局库失败");
+    println!("📖 开局库已保存到: {}", out);
+}
+
+fn print_book(book: &OpeningBook) {
+    println!("\n📖 开局库统计");
+    println!("   棋盘大小: {}x{}", book.width, book.height);
+    println!("   学习步数: {}", book.max_ply);
+    println!("   局面数: {}", book.len());
+    println!("   着法数: {}", book.move_count());
+
+    // 沿着最常见的着法打印主要变化
+    let mut board = gomoku::board::Board::new(
+        "0".repeat(book.width * book.height),
+        book.width,
+        book.height,
+    );
+    println!("\n主要变化:");
+    for ply in 0..book.max_ply {
+        let moves = book.moves(&board);
+        let best = match moves.iter().max_by_key(|mv| mv.games) {
+            Some(mv) => *mv,
+            None => break,
+        };
+        println!(
+            "   {:>2}. ({:>2}, {:>2})  对局: {:>5}  胜率: {:.1}%  (候选 {} 个)",
+            ply + 1,
+            best.x,
+            best.y,
+            best.games,
+            best.win_rate() * 100.0,
+            moves.len()
+        );
+        board.place(best.x, best.y, board.next_player());
+    }
+}
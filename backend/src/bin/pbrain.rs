@@ -0,0 +1,61 @@
+// Gomocup / Piskvork brain - speaks the pbrain protocol on stdin and stdout
+use gomoku::algo::EngineOptions;
+use gomoku::pbrain::Brain;
+use std::io::{self, BufRead, Write};
+
+use clap::{App, Arg};
+
+fn main() {
+    let matches = App::new("pbrain-gomoku")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Gomoku brain for Piskvork compatible managers")
+        .arg(
+            Arg::new("algo")
+                .long("algo")
+                .takes_value(true)
+                .default_value("hybrid")
+                .help("The engine to play with, see gomoku --engines"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .takes_value(true)
+                .help("The search threads"),
+        )
+        .arg(
+            Arg::new("level")
+                .long("level")
+                .takes_value(true)
+                .help("The difficulty level from 1 to 10, full strength by default"),
+        )
+        .get_matches();
+
+    let options = EngineOptions {
+        threads: matches.value_of("threads").map(|v| v.parse().unwrap()),
+        level: matches.value_of("level").map(|v| v.parse().unwrap()),
+        ..EngineOptions::default()
+    };
+    let mut brain = match Brain::new(matches.value_of("algo").unwrap(), &options) {
+        Ok(brain) => brain,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        for answer in brain.handle(&line) {
+            writeln!(stdout, "{}", answer).unwrap();
+        }
+        stdout.flush().unwrap();
+        if brain.ended() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Stub pbrain brain for testing managers - plays the first empty cell, row by row.
+// `--hang` never answers a move, `--occupied` answers with a taken cell,
+// `--log <file>` appends every command received to the file.
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let hang = args.iter().any(|a| a == "--hang");
+    let occupied = args.iter().any(|a| a == "--occupied");
+    let mut log = args
+        .iter()
+        .position(|a| a == "--log")
+        .and_then(|i| args.get(i + 1))
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap()
+        });
+    let (mut width, mut height) = (0, 0);
+    let mut stones: Vec<(usize, usize)> = vec![];
+    let mut in_board = false;
+
+    let stdin = io::stdin();
+    let mut out = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let line = line.trim();
+        if let Some(log) = log.as_mut() {
+            writeln!(log, "{}", line).unwrap();
+        }
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let pair = |s: &str| -> Option<(usize, usize)> {
+            let mut it = s.split(',').map(|v| v.trim().parse::<usize>().ok());
+            Some((it.next()??, it.next()??))
+        };
+        let mut play = false;
+        if in_board {
+            if line == "DONE" {
+                in_board = false;
+                play = true;
+            } else if let Some(cell) = pair(line) {
+                stones.push(cell);
+            }
+        } else {
+            match command {
+                "START" | "RECTSTART" => {
+                    width = rest.split(',').next().unwrap().parse().unwrap();
+                    height = rest.split(',').next_back().unwrap().parse().unwrap();
+                    stones.clear();
+                    writeln!(out, "OK").unwrap();
+                }
+                "RESTART" => {
+                    stones.clear();
+                    writeln!(out, "OK").unwrap();
+                }
+                "BEGIN" => play = true,
+                "TURN" => {
+                    stones.extend(pair(rest));
+                    play = true;
+                }
+                "BOARD" => {
+                    stones.clear();
+                    in_board = true;
+                }
+                "INFO" => (),
+                "ABOUT" => writeln!(out, "name=\"stub\", version=\"1.0\"").unwrap(),
+                "END" => break,
+                _ => writeln!(out, "UNKNOWN {}", command).unwrap(),
+            }
+        }
+        if play && !hang {
+            writeln!(out, "MESSAGE thinking").unwrap();
+            let cell = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .find(|c| occupied == stones.contains(c));
+            match cell {
+                Some((x, y)) => {
+                    stones.push((x, y));
+                    writeln!(out, "{},{}", x, y).unwrap();
+                }
+                None => writeln!(out, "ERROR no move").unwrap(),
+            }
+        }
+        out.flush().unwrap();
+    }
+}
@@ -117,9 +117,21 @@ pub struct Board {
     pub width: usize,
     pub height: usize,
     pub win_len: usize,
+    /// Only lines of exactly `win_len` stones win, longer ones count for nothing
+    pub exact_five: bool,
     cells: Vec<Vec<u8>>,
     at_x: i32,
     at_y: i32,
+    hash: u64,
+}
+
+// Zobrist key of one stone, derived with splitmix64 so no table needs to be stored
+fn zobrist_key(row: usize, col: usize, player: u8) -> u64 {
+    let mut z =
+        (((row as u64) << 16 | col as u64) << 2 | player as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
 }
 
 impl From<String> for Board {
@@ -167,16 +179,102 @@ impl Board {
 
         let win_len = 5;
 
+        let mut hash = 0;
+        for (i, cell) in rows.iter().enumerate() {
+            if *cell != 0 {
+                hash ^= zobrist_key(i / width, i % width, *cell);
+            }
+        }
+
         Self {
             width,
             height,
             win_len,
+            exact_five: false,
             cells: rows.chunks(width).map(|x| x.to_vec()).collect(),
             at_x: -1,
             at_y: -1,
+            hash,
         }
     }
 
+    /// Zobrist hash of the stones on board, kept up to date by `place`
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Whether symmetry `sym` (see `transform`) maps this board onto itself
+    pub fn has_symmetry(&self, sym: usize) -> bool {
+        match sym {
+            0 | 2 | 4 | 5 => true,
+            _ => self.width == self.height && sym < 8,
+        }
+    }
+
+    /* The 8 symmetries of a square board:
+     * 0 identity, 1-3 rotations by 90/180/270 degrees,
+     * 4 mirror columns, 5 mirror rows, 6 transpose, 7 anti-transpose.
+     * Only 0, 2, 4 and 5 apply to boards which are not square.
+     */
+    pub fn transform(&self, sym: usize, row: usize, col: usize) -> (usize, usize) {
+        let (h, w) = (self.height - 1, self.width - 1);
+        match sym {
+            0 => (row, col),
+            1 => (col, h - row),
+            2 => (h - row, w - col),
+            3 => (w - col, row),
+            4 => (row, w - col),
+            5 => (h - row, col),
+            6 => (col, row),
+            7 => (w - col, h - row),
+            _ => panic!("invalid symmetry: {}", sym),
+        }
+    }
+
+    pub fn inverse_transform(&self, sym: usize, row: usize, col: usize) -> (usize, usize) {
+        let inverse = match sym {
+            1 => 3,
+            3 => 1,
+            s => s,
+        };
+        self.transform(inverse, row, col)
+    }
+
+    /// Hash of every symmetric copy of the board which has the same shape, with its symmetry
+    pub fn symmetric_hashes(&self) -> Vec<(u64, usize)> {
+        let mut hashes = [0u64; 8];
+        for i in 0..self.height {
+            for j in 0..self.width {
+                let p = self.cells[i][j];
+                if p == 0 {
+                    continue;
+                }
+                for (sym, hash) in hashes.iter_mut().enumerate() {
+                    if self.has_symmetry(sym) {
+                        let (r, c) = self.transform(sym, i, j);
+                        *hash ^= zobrist_key(r, c, p);
+                    }
+                }
+            }
+        }
+        (0..8)
+            .filter(|sym| self.has_symmetry(*sym))
+            .map(|sym| (hashes[sym], sym))
+            .collect()
+    }
+
+    /// Smallest hash over all symmetric copies of the board, with the symmetry which produced it
+    pub fn canonical_hash(&self) -> (u64, usize) {
+        self.symmetric_hashes().into_iter().min().unwrap()
+    }
+
+    /// Whether both boards have the same size and winning rule, so that a position
+    /// of one can stand for a position of the other
+    pub fn same_rules(&self, other: &Board) -> bool {
+        (self.width, self.height, self.win_len, self.exact_five)
+            == (other.width, other.height, other.win_len, other.exact_five)
+    }
+
     pub fn any_winner(&self) -> Option<u8> {
         for i in 0..self.height {
             for j in 0..self.width {
@@ -196,6 +294,18 @@ impl Board {
         None
     }
 
+    /// Whether the stone at (row, col) is part of a winning line
+    pub fn is_win_at(&self, row: usize, col: usize) -> bool {
+        let p = match self.get(row as i32, col as i32) {
+            Some(p) if p != 0 => p,
+            _ => return false,
+        };
+        cfg::DIRS.iter().any(|d| {
+            let line = self.connect_direction(p, row, col, d[0], d[1], true);
+            (line.count as usize) >= self.win_len && line.space_count == 0
+        })
+    }
+
     pub fn next_player(&self) -> u8 {
         let mut p1 = 0;
         let mut p2 = 0;
@@ -285,7 +395,11 @@ impl Board {
         if ((len + space_count + lefted_space) as usize) < self.win_len {
             open_count = 0;
         }
-        if (len as usize) >= self.win_len {
+        if (len as usize) > self.win_len && space_count == 0 && self.exact_five {
+            // An overline, as good as a blocked line
+            len = (self.win_len - 1) as u32;
+            open_count = 0;
+        } else if (len as usize) >= self.win_len {
             if space_count == 0 {
                 len = self.win_len as u32;
                 open_count = 2;
@@ -377,6 +491,13 @@ impl Board {
     }
 
     pub fn place(&mut self, row: usize, col: usize, player: u8) {
+        let prev = self.cells[row][col];
+        if prev != 0 {
+            self.hash ^= zobrist_key(row, col, prev);
+        }
+        if player != 0 {
+            self.hash ^= zobrist_key(row, col, player);
+        }
         self.cells[row][col] = player;
         if player != 0 {
             self.at_x = row as i32;
@@ -574,16 +695,34 @@ impl Board {
     }
 
     pub fn print(&self) {
+        self.print_labeled(|col| format!("{:X}", col), |row| format!("{:X}", row));
+    }
+
+    /// Print with the columns lettered from `a` and the rows numbered from 1 at the bottom,
+    /// the cells are named like `h8`
+    pub fn print_algebraic(&self) {
+        let height = self.height;
+        self.print_labeled(
+            |col| ((b'a' + col as u8) as char).to_string(),
+            |row| (height - row).to_string(),
+        );
+    }
+
+    fn print_labeled(
+        &self,
+        col_label: impl Fn(usize) -> String,
+        row_label: impl Fn(usize) -> String,
+    ) {
         use yansi::Paint;
 
         print!("    ");
         for j in 0..self.width {
-            print!("{:X} ", j);
+            print!("{} ", col_label(j));
         }
         println!();
 
         for i in 0..self.height {
-            print!("  {:X} ", i);
+            print!("{:>3} ", row_label(i));
 
             for j in 0..self.width {
                 let last_placed = i == self.at_x as usize && j == self.at_y as usize;
@@ -717,6 +856,69 @@ mod tests {
         assert_eq!(copy.get(0, 0), Some(0));
     }
 
+    #[test]
+    fn test_board_hash() {
+        let mut board = Board::new_default();
+        let empty = board.hash();
+        board.place(7, 7, 1);
+        board.place(7, 8, 2);
+        let same = Board::new(board.to_string(), BOARD_WIDTH, BOARD_HEIGHT);
+        assert_eq!(board.hash(), same.hash());
+        assert_ne!(board.hash(), empty);
+
+        board.place(7, 8, 1);
+        assert_ne!(board.hash(), same.hash());
+        board.place(7, 8, 0);
+        board.place(7, 7, 0);
+        assert_eq!(board.hash(), empty);
+    }
+
+    #[test]
+    fn test_canonical_hash() {
+        let mut board = Board::new_default();
+        board.place(3, 4, 1);
+        board.place(5, 5, 2);
+        let (hash, sym) = board.canonical_hash();
+        for s in 0..8 {
+            let mut copy = Board::new_default();
+            copy.place(board.transform(s, 3, 4).0, board.transform(s, 3, 4).1, 1);
+            copy.place(board.transform(s, 5, 5).0, board.transform(s, 5, 5).1, 2);
+            assert_eq!(copy.canonical_hash().0, hash);
+            let (r, c) = board.transform(s, 3, 4);
+            assert_eq!(board.inverse_transform(s, r, c), (3, 4));
+        }
+        let (r, c) = board.transform(sym, 3, 4);
+        assert_eq!(board.inverse_transform(sym, r, c), (3, 4));
+
+        let mut other = Board::new_default();
+        other.place(3, 4, 2);
+        other.place(5, 5, 1);
+        assert_ne!(other.canonical_hash().0, hash);
+
+        let wide = Board::new(String::from("100000000000"), 6, 2);
+        assert!(!wide.has_symmetry(1));
+        assert_eq!(wide.transform(2, 0, 0), (1, 5));
+    }
+
+    #[test]
+    fn test_exact_five() {
+        let mut board = Board::new_default();
+        for col in 3..9 {
+            if col != 5 {
+                board.place(7, col, 1);
+            }
+        }
+        board.exact_five = true;
+        board.place(7, 5, 1);
+        assert_eq!(board.any_winner(), None);
+        assert!(board.eval_pos(1, 7, 5) < 100000);
+        board.place(7, 3, 0);
+        assert_eq!(board.any_winner(), Some(1));
+        board.exact_five = false;
+        board.place(7, 3, 1);
+        assert_eq!(board.any_winner(), Some(1));
+    }
+
     #[test]
     fn test_line() {
         let mut line = Line::new(5, 0, 0, crate::utils::WIN_LEN);
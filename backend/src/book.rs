@@ -0,0 +1,328 @@
+#![allow(dead_code)]
+// Opening book - moves learned from recorded games, keyed by canonical position
+use super::board::*;
+use super::game_record::GameRecord;
+use super::rng::GameRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, OnceLock, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BookMove {
+    pub x: usize,
+    pub y: usize,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+}
+
+impl BookMove {
+    fn new(x: usize, y: usize) -> Self {
+        Self {
+            x,
+            y,
+            games: 0,
+            wins: 0,
+            draws: 0,
+        }
+    }
+
+    /// Score of the side playing the move, a draw counts as half a win
+    pub fn win_rate(&self) -> f32 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        (self.wins as f32 + self.draws as f32 * 0.5) / self.games as f32
+    }
+
+    // Smoothed win rate used for picking moves, so a single lucky game does not dominate
+    fn rank(&self) -> f32 {
+        (self.wins as f32 + self.draws as f32 * 0.5 + 1.0) / (self.games as f32 + 2.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpeningBook {
+    pub width: usize,
+    pub height: usize,
+    /// Only the first `max_ply` moves of every game are learned
+    pub max_ply: usize,
+    /// Moves seen in fewer games are not played by `lookup`
+    pub min_games: u32,
+    // Moves are stored in the coordinates of the canonical position
+    positions: HashMap<u64, Vec<BookMove>>,
+}
+
+impl OpeningBook {
+    pub fn new(width: usize, height: usize, max_ply: usize) -> Self {
+        Self {
+            width,
+            height,
+            max_ply,
+            min_games: 2,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// Number of positions in the book
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.positions.values().map(|moves| moves.len()).sum()
+    }
+
+    /// Learn the opening of a game. The moves are replayed on an empty board,
+    /// the result decides whether each move counts as a win, loss or draw.
+    pub fn add_game(&mut self, record: &GameRecord) {
+        let mut board = self.empty_board();
+        for state in record.states.iter().take(self.max_ply) {
+            if board.get(state.move_x as i32, state.move_y as i32) != Some(0) {
+                break;
+            }
+            let (hash, sym) = self.canonical(&board, state.move_x, state.move_y);
+            let (x, y) = board.transform(sym, state.move_x, state.move_y);
+            let entry = self.entry(hash, x, y);
+            entry.games += 1;
+            match record.winner {
+                Some(w) if w == state.player => entry.wins += 1,
+                None => entry.draws += 1,
+                _ => (),
+            }
+            board.place(state.move_x, state.move_y, state.player);
+            if board.any_winner().is_some() {
+                break;
+            }
+        }
+    }
+
+    /// Add the statistics of `other`, which must be built for the same board size
+    pub fn merge(&mut self, other: &OpeningBook) -> Result<(), String> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(format!(
+                "can not merge a {}x{} book into a {}x{} book",
+                other.width, other.height, self.width, self.height
+            ));
+        }
+        self.max_ply = std::cmp::max(self.max_ply, other.max_ply);
+        for (hash, moves) in other.positions.iter() {
+            for mv in moves {
+                let entry = self.entry(*hash, mv.x, mv.y);
+                entry.games += mv.games;
+                entry.wins += mv.wins;
+                entry.draws += mv.draws;
+            }
+        }
+        Ok(())
+    }
+
+    /// Book moves for `board` in its own coordinates, best first
+    pub fn moves(&self, board: &Board) -> Vec<BookMove> {
+        if (board.width, board.height) != (self.width, self.height) {
+            return vec![];
+        }
+        let (hash, sym) = board.canonical_hash();
+        let mut moves: Vec<BookMove> = match self.positions.get(&hash) {
+            Some(moves) => moves
+                .iter()
+                .map(|mv| {
+                    let (x, y) = board.inverse_transform(sym, mv.x, mv.y);
+                    BookMove { x, y, ..*mv }
+                })
+                // A hash collision could point at an occupied cell
+                .filter(|mv| board.get(mv.x as i32, mv.y as i32) == Some(0))
+                .collect(),
+            None => vec![],
+        };
+        moves.sort_by(|a, b| b.rank().partial_cmp(&a.rank()).unwrap());
+        moves
+    }
+
+    /// The best book move with at least `min_games` games
+    pub fn lookup(&self, board: &Board) -> Option<Move> {
+        self.moves(board)
+            .into_iter()
+            .find(|mv| mv.games >= self.min_games)
+            .map(|mv| Move::new(mv.x, mv.y, 0, 0))
+    }
+
+    /// Follow the book from the empty board for at most `max_moves` moves,
+    /// choosing each move with a probability proportional to its games
+    pub fn random_line(&self, max_moves: usize, rng: &mut GameRng) -> Vec<(usize, usize)> {
+        let mut board = self.empty_board();
+        let mut line = vec![];
+        while line.len() < max_moves {
+            let moves = self.moves(&board);
+            if moves.is_empty() {
+                break;
+            }
+            let total: u32 = moves.iter().map(|mv| mv.games).sum();
+            let mut pick = rng.below(total as usize) as u32;
+            let mut mv = moves[0];
+            for m in moves.iter() {
+                if pick < m.games {
+                    mv = *m;
+                    break;
+                }
+                pick -= m.games;
+            }
+            board.place(mv.x, mv.y, board.next_player());
+            line.push((mv.x, mv.y));
+        }
+        line
+    }
+
+    fn empty_board(&self) -> Board {
+        Board::new(
+            "0".repeat(self.width * self.height),
+            self.width,
+            self.height,
+        )
+    }
+
+    // Canonical hash of `board` and the symmetry to use for the move at (x, y).
+    // A symmetric position maps to the same hash in several ways, equivalent
+    // moves are folded together by taking the smallest of their images.
+    fn canonical(&self, board: &Board, x: usize, y: usize) -> (u64, usize) {
+        let hashes = board.symmetric_hashes();
+        let hash = hashes.iter().min().unwrap().0;
+        let sym = hashes
+            .iter()
+            .filter(|(h, _)| *h == hash)
+            .map(|(_, sym)| *sym)
+            .min_by_key(|sym| board.transform(*sym, x, y))
+            .unwrap();
+        (hash, sym)
+    }
+
+    fn entry(&mut self, hash: u64, x: usize, y: usize) -> &mut BookMove {
+        let moves = self.positions.entry(hash).or_default();
+        let idx = match moves.iter().position(|mv| mv.x == x && mv.y == y) {
+            Some(idx) => idx,
+            None => {
+                moves.push(BookMove::new(x, y));
+                moves.len() - 1
+            }
+        };
+        &mut moves[idx]
+    }
+}
+
+// Book consulted by `algo::gomoku_solve`, loaded from `GOMOKU_BOOK` on first use
+fn global_book() -> &'static RwLock<Option<Arc<OpeningBook>>> {
+    static BOOK: OnceLock<RwLock<Option<Arc<OpeningBook>>>> = OnceLock::new();
+    BOOK.get_or_init(|| {
+        let book = env::var("GOMOKU_BOOK").ok().and_then(|path| {
+            OpeningBook::load(&path)
+                .map_err(|e| eprintln!("failed to load opening book {}: {}", path, e))
+                .ok()
+        });
+        RwLock::new(book.map(Arc::new))
+    })
+}
+
+/// Replace the book used by `algo::gomoku_solve`, `None` disables it
+pub fn set_global_book(book: Option<OpeningBook>) {
+    *global_book().write().unwrap() = book.map(Arc::new);
+}
+
+pub fn global_lookup(board: &Board) -> Option<Move> {
+    let book = global_book().read().unwrap().clone()?;
+    book.lookup(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_record::GameState;
+
+    fn make_game(moves: &[(usize, usize)], winner: Option<u8>) -> GameRecord {
+        let mut record = GameRecord::new("minimax".to_string(), "minimax".to_string());
+        for (i, (x, y)) in moves.iter().enumerate() {
+            record.add_state(GameState {
+                board: String::new(),
+                player: if i % 2 == 0 { 1 } else { 2 },
+                move_x: *x,
+                move_y: *y,
+                eval_score: 0,
+                step: i + 1,
+            });
+        }
+        record.set_winner(winner);
+        record
+    }
+
+    #[test]
+    fn test_book_learns_symmetric_games() {
+        let mut book = OpeningBook::new(15, 15, 4);
+        // The same opening, mirrored, won twice by the first player
+        book.add_game(&make_game(&[(7, 7), (6, 8), (5, 5)], Some(1)));
+        book.add_game(&make_game(&[(7, 7), (8, 6), (9, 9)], Some(1)));
+        book.add_game(&make_game(&[(7, 7), (7, 8)], Some(2)));
+
+        let mut board = Board::new_default();
+        assert_eq!(book.moves(&board)[0].games, 3);
+        board.place(7, 7, 1);
+        let moves = book.moves(&board);
+        assert_eq!(moves.len(), 2);
+        // The single winning reply ranks above the reply which lost twice
+        assert_eq!((moves[0].games, moves[0].win_rate()), (1, 1.0));
+        assert_eq!((moves[1].games, moves[1].wins), (2, 0));
+
+        // Only the losing reply has been seen often enough
+        let mv = book.lookup(&board).unwrap();
+        assert_eq!((mv.x.abs_diff(7), mv.y.abs_diff(7)), (1, 1));
+        book.min_games = 1;
+        let mv = book.lookup(&board).unwrap();
+        assert_eq!(mv.x.abs_diff(7) + mv.y.abs_diff(7), 1);
+
+        // (5, 5) and (9, 9) are the same move in this symmetric position
+        board.place(8, 6, 2);
+        let moves = book.moves(&board);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].games, 2);
+        assert!((moves[0].x, moves[0].y) == (5, 5) || (moves[0].x, moves[0].y) == (9, 9));
+    }
+
+    #[test]
+    fn test_book_merge_and_save() {
+        let mut book = OpeningBook::new(15, 15, 4);
+        book.add_game(&make_game(&[(7, 7), (6, 8)], Some(1)));
+        let mut other = OpeningBook::new(15, 15, 6);
+        other.add_game(&make_game(&[(7, 7), (6, 8)], None));
+        book.merge(&other).unwrap();
+        assert_eq!(book.max_ply, 6);
+        assert_eq!(book.len(), 2);
+
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mv = book.moves(&board)[0];
+        assert_eq!((mv.games, mv.wins, mv.draws), (2, 0, 1));
+        assert!(book.merge(&OpeningBook::new(9, 9, 4)).is_err());
+
+        let path = env::temp_dir().join("gomoku_test_book.json");
+        let path = path.to_str().unwrap();
+        book.save(path).unwrap();
+        let loaded = OpeningBook::load(path).unwrap();
+        assert_eq!(loaded.move_count(), book.move_count());
+        fs::remove_file(path).ok();
+
+        assert_eq!(book.random_line(5, &mut GameRng::new(1)).len(), 2);
+    }
+}
@@ -1,28 +1,42 @@
 #![allow(dead_code)]
 #![warn(unused_variables)]
 use super::algo;
+use super::algo::{Engine, EngineOptions};
 use super::board::*;
+use super::difficulty::Difficulty;
 use super::minimax;
 use super::monte;
+use super::piskvork::ExternalEngine;
+use super::search::{SearchControl, SearchLimits, SearchStats, Timer};
+use super::utils::*;
 use build_timestamp::build_time;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::process::Command;
-#[cfg(feature = "server")]
-use std::time::Instant;
+use std::env;
 
 build_time!("%A %Y-%m-%d/%H:%M:%S");
 
 #[derive(Serialize, Deserialize)]
 struct Body {
     ai_player: u8,
+    // Milliseconds
     cpu_time: String,
-    eval_count: u32,
+    eval_count: u64,
     move_c: usize,
     move_r: usize,
-    node_count: u32,
+    node_count: u64,
+    // Threads the search ran on, 0 when no search was needed
     num_threads: i32,
+    // Depth reached
     search_depth: i32,
+    simulations: u64,
+    tt_hits: u64,
+    nodes_per_second: u64,
+    // Limits the search ran with, unset limits are `null`
+    depth_limit: Option<i32>,
+    node_limit: Option<u64>,
+    time_limit_ms: Option<u64>,
+    // Difficulty level the move was played at, `null` for full strength
+    level: Option<u8>,
     winning_player: u8,
     score: i32,
     build: String,
@@ -31,25 +45,50 @@ struct Body {
 #[derive(Serialize, Deserialize)]
 struct Message {
     message: String,
-    result: Body,
+    // `null` when the move could not be searched, `message` says why
+    result: Option<Body>,
 }
 
 pub fn solve_it(input: &str, algo_type: &str, width: usize, height: usize) -> String {
+    let options = EngineOptions::default();
+    solve_it_with(
+        input,
+        algo_type,
+        width,
+        height,
+        &options,
+        &SearchControl::default(),
+    )
+}
+
+pub fn solve_it_with(
+    input: &str,
+    algo_type: &str,
+    width: usize,
+    height: usize,
+    options: &EngineOptions,
+    control: &SearchControl,
+) -> String {
     let mut board = Board::new(input.to_string(), width, height);
     let player = board.next_player();
     let mut winner = 0;
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "server")] {
-          let start = Instant::now();
-        }
-    };
+    let timer = Timer::start();
+    let mut limits = options.limits();
+    let mut stats = SearchStats::default();
     let mut score = 0;
     let mut row = 0;
     let mut col = 0;
     if let Some(w) = board.any_winner() {
         winner = w;
     } else {
-        let mv = algo::gomoku_solve(input, algo_type, width, height);
+        let res = match algo::gomoku_solve_with(input, algo_type, width, height, options, control) {
+            Ok(res) if res.has_move() => res,
+            Ok(_) => return error_message(&format!("no move from {}", algo_type)),
+            Err(e) => return error_message(&e.to_string()),
+        };
+        limits = res.limits;
+        stats = res.stats;
+        let mv = res.best_move;
         row = mv.x;
         col = mv.y;
         score = mv.score;
@@ -59,194 +98,99 @@ pub fn solve_it(input: &str, algo_type: &str, width: usize, height: usize) -> St
             winner = w;
         }
     }
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "server")] {
-          let duration = start.elapsed();
-        } else {
-          let duration = 0;
-        }
-    };
-    println!("duration: {:?}", duration);
+    // Also counts the time of book lookups, which do not search
+    stats.elapsed_ms = timer.elapsed_ms();
+    println!("duration: {} ms", stats.elapsed_ms);
     let result = Message {
         message: String::from("ok"),
-        result: Body {
+        result: Some(Body {
             ai_player: player,
-            cpu_time: format!("{:?}", duration),
-            eval_count: 0,
+            cpu_time: stats.elapsed_ms.to_string(),
+            eval_count: stats.evaluations,
             move_c: col,
             move_r: row,
-            node_count: 0,
-            num_threads: 1,
-            search_depth: 9,
+            node_count: stats.nodes,
+            num_threads: stats.threads as i32,
+            search_depth: stats.depth,
+            simulations: stats.simulations,
+            tt_hits: stats.tt_hits,
+            nodes_per_second: stats.nodes_per_second(),
+            depth_limit: limits.depth,
+            node_limit: limits.nodes,
+            time_limit_ms: limits.time_ms,
+            level: options.level.map(|l| Difficulty::new(l).level()),
             winning_player: winner,
             score: score,
             build: BUILD_TIME.to_string(),
-        },
+        }),
     };
     serde_json::to_string(&result).unwrap()
 }
 
-pub fn battle_other_self() {
-    let mut board = Board::new_default();
-    let opponent = 1;
-    let me = 2;
-    board.place(7, 7, 1);
-
-    loop {
-        let output = Command::new("gomoku")
-            .arg("-s")
-            .arg(board.to_string())
-            .arg("-p")
-            .arg(me.to_string())
-            .output()
-            .expect("failed to execute process");
-        let command_res = String::from_utf8(output.stdout).unwrap();
-        //println!("output: {:?}", command_res);
-        let json: Value = serde_json::from_str(command_res.as_str()).unwrap();
-        let row_str: String = Value::to_string(&json["result"]["move_r"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-        let col_str: String = Value::to_string(&json["result"]["move_c"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-
-        let row = row_str.parse::<i32>().unwrap();
-        let col = col_str.parse::<i32>().unwrap();
-        println!("+ row: {:?} col: {:?}", row, col);
-        board.place(row as usize, col as usize, me);
-        board.print();
-        if let Some(w) = board.any_winner() {
-            println!("winner is: {} !!!!", w);
-            break;
-        }
+pub fn error_message(message: &str) -> String {
+    let result = Message {
+        message: message.to_string(),
+        result: None,
+    };
+    serde_json::to_string(&result).unwrap()
+}
 
-        //let args = format!("-s {} -p {}", board.to_string(), opponent);
-        let output = Command::new("gomoku")
-            .arg("-s")
-            .arg(board.to_string())
-            .arg("-p")
-            .arg(opponent.to_string())
-            .output()
-            .expect("failed to execute process");
-        let command_res = String::from_utf8(output.stdout).unwrap();
+/// The engines `solve_it` accepts as `algo_type`, as JSON
+pub fn engines_json() -> String {
+    serde_json::to_string(&algo::list_engines()).unwrap()
+}
 
-        let json: Value = serde_json::from_str(command_res.as_str()).unwrap();
-        let row_str: String = Value::to_string(&json["result"]["move_r"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-        let col_str: String = Value::to_string(&json["result"]["move_c"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
+// The external brain of the battles, `GOMOKU_BRAIN` or the pbrain binary of this crate
+fn external_brain() -> Box<dyn Engine> {
+    let command = env::var("GOMOKU_BRAIN").unwrap_or_else(|_| "pbrain-gomoku".to_string());
+    Box::new(ExternalEngine::new("pbrain", &command))
+}
 
-        let row = row_str.parse::<i32>().unwrap();
-        let col = col_str.parse::<i32>().unwrap();
-        println!("o row: {:?} col: {:?}", row, col);
-        board.place(row as usize, col as usize, opponent);
-        board.print();
-        if let Some(w) = board.any_winner() {
-            println!("winner is: {} !!!!", w);
-            break;
-        }
-    }
+fn minimax_engine() -> Box<dyn Engine> {
+    algo::create_engine("minimax", &EngineOptions::default()).unwrap()
 }
 
-pub fn battle() {
+// Plays a game after the first stone, `first` moves next, until a win or a failed move
+fn battle_engines(first: Box<dyn Engine>, second: Box<dyn Engine>, marks: [&str; 2]) {
     let mut board = Board::new_default();
-    let opponent = 1;
-    let me = 2;
     board.place(7, 7, 1);
-    let mut runner = minimax::MiniMax::new(2, 4);
-    //println!("board: {}", board.to_string());
-    loop {
-        let (_, row, col) = runner.run_heuristic(&mut board, me);
-        println!("+ row: {:?} col: {:?}", row, col);
-        board.place(row, col, me);
-        board.print();
-        if let Some(w) = board.any_winner() {
-            println!("winner is: {} !!!!", w);
+    let mut engines = [first, second];
+    let limits = SearchLimits::depth(4);
+    for turn in 0.. {
+        let engine = &mut engines[turn % 2];
+        let player = board.next_player();
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        let mv = res.best_move;
+        if !res.has_move() || board.get(mv.x as i32, mv.y as i32) != Some(0) {
+            // A side which cannot move loses
+            println!("no move from {}", engine.name());
+            println!("winner is: {} !!!!", cfg::opponent(player));
             break;
         }
-        //let args = format!("-s {} -p {}", board.to_string(), opponent);
-        let output = Command::new("gomoku")
-            .arg("-s")
-            .arg(board.to_string())
-            .arg(opponent.to_string())
-            .output()
-            .expect("failed to execute process");
-        let command_res = String::from_utf8(output.stdout).unwrap();
-        //println!("output: {:?}", command_res);
-        let json: Value = serde_json::from_str(command_res.as_str()).unwrap();
-        let row_str: String = Value::to_string(&json["result"]["move_r"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-        let col_str: String = Value::to_string(&json["result"]["move_c"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-
-        let row = row_str.parse::<i32>().unwrap();
-        let col = col_str.parse::<i32>().unwrap();
-        println!("o row: {:?} col: {:?}", row, col);
-        board.place(row as usize, col as usize, opponent);
+        println!("{} row: {:?} col: {:?}", marks[turn % 2], mv.x, mv.y);
+        board.place(mv.x, mv.y, player);
         board.print();
         if let Some(w) = board.any_winner() {
             println!("winner is: {} !!!!", w);
             break;
         }
+        if board.empty_cells_count() == 0 {
+            break;
+        }
     }
 }
 
-pub fn rev_battle() {
-    let mut board = Board::new_default();
-    let opponent = 2;
-    let me = 1;
-    board.place(7, 7, 1);
-    let mut runner = minimax::MiniMax::new(2, 4);
-    loop {
-        //let args = format!("-s {} -p {}", board.to_string(), opponent);
-        let output = Command::new("gomoku")
-            .arg("-s")
-            .arg(board.to_string())
-            .arg(opponent.to_string())
-            .output()
-            .expect("failed to execute process");
-
-        let command_res = String::from_utf8(output.stdout).unwrap();
-        //println!("output: {:?}", command_res);
-        let json: Value = serde_json::from_str(command_res.as_str()).unwrap();
-        let row_str: String = Value::to_string(&json["result"]["move_r"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
-        let col_str: String = Value::to_string(&json["result"]["move_c"])
-            .chars()
-            .filter(|&x| x != '\"')
-            .collect();
+pub fn battle_other_self() {
+    battle_engines(external_brain(), external_brain(), ["+", "o"]);
+}
 
-        let row = row_str.parse::<i32>().unwrap();
-        let col = col_str.parse::<i32>().unwrap();
-        println!("+ row: {:?} col: {:?}", row, col);
-        board.place(row as usize, col as usize, opponent);
-        board.print();
-        if let Some(w) = board.any_winner() {
-            println!("winner is: {} !!!!", w);
-            break;
-        }
+pub fn battle() {
+    battle_engines(minimax_engine(), external_brain(), ["+", "o"]);
+}
 
-        let (_, row, col) = runner.run_heuristic(&mut board, me);
-        println!("o row: {:?} col: {:?}", row, col);
-        board.place(row, col, me);
-        board.print();
-        if let Some(w) = board.any_winner() {
-            println!("winner is: {} !!!!", w);
-            break;
-        }
-    }
+pub fn rev_battle() {
+    battle_engines(external_brain(), minimax_engine(), ["+", "o"]);
 }
 
 pub fn battle_self() {
@@ -283,9 +227,17 @@ pub fn battle_monte() {
     let me = 1;
     board.place(7, 7, 1);
     //println!("board: {}", board.to_string());
+    // Each side keeps its tree between moves
+    let mut monte_opponent = monte::MonteCarlo::new(board.clone(), opponent, 4000);
+    let mut monte_me = monte::MonteCarlo::new(board.clone(), me, 4000);
     loop {
-        let mut monte = monte::MonteCarlo::new(board.clone(), opponent, 4000);
-        let mv = monte.search_move();
+        monte_opponent.advance(&board);
+        let res = monte_opponent.search();
+        if !res.has_move() {
+            println!("no move left, it is a draw");
+            break;
+        }
+        let mv = res.best_move;
         let row = mv.x;
         let col = mv.y;
         board.place(row, col, opponent);
@@ -295,8 +247,13 @@ pub fn battle_monte() {
             break;
         }
 
-        let mut monte = monte::MonteCarlo::new(board.clone(), me, 4000);
-        let mv = monte.search_move();
+        monte_me.advance(&board);
+        let res = monte_me.search();
+        if !res.has_move() {
+            println!("no move left, it is a draw");
+            break;
+        }
+        let mv = res.best_move;
         let row = mv.x;
         let col = mv.y;
         //let (_, row, col) = runner.run_heuristic(&mut board, me);
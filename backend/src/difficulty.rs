@@ -0,0 +1,298 @@
+#![allow(dead_code)]
+// Difficulty levels - weaker play for casual players
+use super::algo::Engine;
+use super::board::*;
+use super::rng::{random_seed, GameRng};
+use super::search::{SearchControl, SearchLimits, SearchResult, StopToken};
+use super::threats::{four_moves, winning_cells};
+use super::utils::*;
+
+pub const MIN_LEVEL: u8 = 1;
+pub const MAX_LEVEL: u8 = 10;
+// From this level on a five is always made or blocked
+const FORCED_MOVE_LEVEL: u8 = 4;
+// From this level on a four of the opponent is always blocked, and so is a three
+// which it could turn into one
+const THREAT_MOVE_LEVEL: u8 = 7;
+
+/* Playing strength from 1 (weakest) to 10 (full strength). Below the top level
+ * the searches are shallower and shorter, and now and then the move is drawn
+ * from the best candidates instead of taking the best one.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Difficulty {
+    level: u8,
+}
+
+impl Difficulty {
+    /// Levels out of range are clamped to `MIN_LEVEL..=MAX_LEVEL`
+    pub fn new(level: u8) -> Self {
+        Self {
+            level: level.clamp(MIN_LEVEL, MAX_LEVEL),
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Deepest MiniMax iteration, `None` at the top level
+    pub fn depth(&self) -> Option<i32> {
+        self.below_max(|level| (level as i32 + 1) / 2)
+    }
+
+    /// Simulations for MonteCarlo and positions for MiniMax, `None` at the top level
+    pub fn nodes(&self) -> Option<u64> {
+        self.below_max(|level| 30 * level as u64 * level as u64)
+    }
+
+    /// Chance of not playing the best move
+    pub fn mistake_chance(&self) -> f64 {
+        (MAX_LEVEL - self.level) as f64 / 12.0
+    }
+
+    /// Number of candidates a move is drawn from, the best one included
+    pub fn top_n(&self) -> usize {
+        1 + (MAX_LEVEL - self.level + 1) as usize / 2
+    }
+
+    /// Softmax temperature over the ranks of the candidates, higher is flatter
+    pub fn temperature(&self) -> f64 {
+        (MAX_LEVEL + 1 - self.level) as f64 / 4.0
+    }
+
+    /// Whether wins and blocks of a five are always played
+    pub fn keeps_forced_moves(&self) -> bool {
+        self.level >= FORCED_MOVE_LEVEL
+    }
+
+    /// Whether the answers to the opponent's threes are always played too
+    pub fn keeps_threat_moves(&self) -> bool {
+        self.level >= THREAT_MOVE_LEVEL
+    }
+
+    /// `limits` with the unset depth and node limits taken from the level
+    pub fn limits(&self, limits: &SearchLimits) -> SearchLimits {
+        SearchLimits {
+            depth: limits.depth.or_else(|| self.depth()),
+            nodes: limits.nodes.or_else(|| self.nodes()),
+            ..*limits
+        }
+    }
+
+    fn below_max<T>(&self, f: impl Fn(u8) -> T) -> Option<T> {
+        if self.level < MAX_LEVEL {
+            Some(f(self.level))
+        } else {
+            None
+        }
+    }
+
+    // Index drawn from `count` candidates, the weight of rank i is exp(-i / temperature)
+    fn draw(&self, count: usize, rng: &mut GameRng) -> usize {
+        let weights: Vec<f64> = (0..count)
+            .map(|i| (-(i as f64) / self.temperature()).exp())
+            .collect();
+        let mut rest: f64 = weights.iter().sum();
+        for (i, w) in weights.iter().enumerate() {
+            if rng.chance(w / rest) {
+                return i;
+            }
+            rest -= w;
+        }
+        count - 1
+    }
+}
+
+/// An engine playing at a difficulty level, see `EngineOptions::level`
+pub struct LeveledEngine {
+    inner: Box<dyn Engine>,
+    difficulty: Difficulty,
+    seed: u64,
+    rng: GameRng,
+    board: Board,
+}
+
+impl LeveledEngine {
+    pub fn new(inner: Box<dyn Engine>, difficulty: Difficulty, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(random_seed);
+        Self {
+            inner,
+            difficulty,
+            seed,
+            rng: GameRng::new(seed),
+            board: Board::new_default(),
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    // Whether the position has a five to make or to block, or with `threats` a
+    // three of the opponent which becomes a four on its next move
+    fn is_forced(&self, threats: bool) -> bool {
+        let player = self.board.next_player();
+        let opponent = cfg::opponent(player);
+        !winning_cells(&self.board, player).is_empty()
+            || !winning_cells(&self.board, opponent).is_empty()
+            || (threats && !four_moves(&self.board, opponent).is_empty())
+    }
+
+    // The best move of the engine, or another one of the best candidates
+    fn choose(&mut self, best: Move) -> Move {
+        if self.difficulty.keeps_forced_moves()
+            && self.is_forced(self.difficulty.keeps_threat_moves())
+        {
+            return best;
+        }
+        if !self.rng.chance(self.difficulty.mistake_chance()) {
+            return best;
+        }
+        let player = self.board.next_player();
+        let mut candidates = vec![best];
+        candidates.extend(
+            self.board
+                .gen_ordered_moves(player)
+                .into_iter()
+                .filter(|m| (m.x, m.y) != (best.x, best.y)),
+        );
+        candidates.truncate(self.difficulty.top_n());
+        candidates[self.difficulty.draw(candidates.len(), &mut self.rng)]
+    }
+}
+
+impl Engine for LeveledEngine {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn new_game(&mut self) {
+        self.inner.new_game();
+        self.board = Board::new_default();
+        self.rng = GameRng::new(self.seed);
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+        self.inner.set_position(board);
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.inner.set_control(control);
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.inner.stop_token()
+    }
+
+    fn predicted_reply(&self) -> Option<Move> {
+        self.inner.predicted_reply()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let mut res = self.inner.search(&self.difficulty.limits(limits));
+        if res.has_move() {
+            res.best_move = self.choose(res.best_move);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algo::{create_engine, EngineOptions};
+
+    #[test]
+    fn test_difficulty_levels() {
+        let weakest = Difficulty::new(0);
+        let strongest = Difficulty::new(20);
+        assert_eq!((weakest.level(), strongest.level()), (1, 10));
+        assert!(weakest.nodes().unwrap() < Difficulty::new(5).nodes().unwrap());
+        assert_eq!((strongest.depth(), strongest.nodes()), (None, None));
+        assert_eq!(strongest.mistake_chance(), 0.0);
+        assert!(weakest.top_n() > Difficulty::new(9).top_n());
+        let limits = Difficulty::new(3).limits(&SearchLimits::depth(4));
+        assert_eq!((limits.depth, limits.nodes), (Some(4), Some(270)));
+
+        // Forced blocks are kept from FORCED_MOVE_LEVEL on
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        board.place(7, 10, 1);
+        board.place(7, 6, 2);
+        board.place(8, 8, 2);
+        board.place(8, 9, 2);
+        for seed in 0..10 {
+            let options = EngineOptions {
+                level: Some(FORCED_MOVE_LEVEL),
+                seed: Some(seed),
+                threads: Some(1),
+                ..EngineOptions::default()
+            };
+            let mut engine = create_engine("minimax", &options).unwrap();
+            engine.set_position(&board);
+            let res = engine.search(&options.limits());
+            assert_eq!((res.best_move.x, res.best_move.y), (7, 11));
+            assert!(res.limits.depth.unwrap() <= 2);
+        }
+
+        // The weakest level does not always play the best move
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(8, 8, 2);
+        let moves: Vec<(usize, usize)> = (0..20)
+            .map(|seed| {
+                let options = EngineOptions {
+                    level: Some(MIN_LEVEL),
+                    seed: Some(seed),
+                    threads: Some(1),
+                    ..EngineOptions::default()
+                };
+                let mut engine = create_engine("minimax", &options).unwrap();
+                engine.set_position(&board);
+                let best = engine.search(&options.limits()).best_move;
+                (best.x, best.y)
+            })
+            .collect();
+        assert!(moves.iter().any(|m| *m != moves[0]));
+    }
+
+    #[test]
+    fn test_threat_moves() {
+        // Player 1 has an open three, player 2 has to answer it
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        board.place(8, 8, 2);
+        board.place(9, 9, 2);
+        let options = EngineOptions {
+            seed: Some(1),
+            ..EngineOptions::default()
+        };
+        let engine = |level| {
+            let inner = create_engine("one_ply", &options).unwrap();
+            let mut engine = LeveledEngine::new(inner, Difficulty::new(level), Some(1));
+            engine.set_position(&board);
+            engine
+        };
+        assert!(!engine(FORCED_MOVE_LEVEL).is_forced(false));
+        assert!(engine(THREAT_MOVE_LEVEL).is_forced(true));
+
+        // The block is kept whatever the draws
+        let mut leveled = engine(THREAT_MOVE_LEVEL);
+        let best = Move::new(7, 10, 0, 0);
+        for _ in 0..50 {
+            let mv = leveled.choose(best);
+            assert_eq!((mv.x, mv.y), (7, 10));
+        }
+        let mut weaker = engine(THREAT_MOVE_LEVEL - 1);
+        assert!((0..50).any(|_| {
+            let mv = weaker.choose(best);
+            (mv.x, mv.y) != (7, 10)
+        }));
+    }
+}
@@ -0,0 +1,215 @@
+#![allow(dead_code)]
+// Search tree export for debugging, written as Graphviz DOT or JSON
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+
+/// Which part of a search tree is exported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    /// Levels of moves below the root
+    pub max_depth: usize,
+    /// Nodes visited fewer times are left out together with their subtrees
+    pub min_visits: u64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            min_visits: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportNode {
+    /// Move leading to this node as (row, col), `None` at the root
+    pub mv: Option<(usize, usize)>,
+    /// Player to move in this node
+    pub to_move: u8,
+    /// Simulations through the node for MonteCarlo, positions searched below it for MiniMax
+    pub visits: u64,
+    /// Rollouts won and lost by the player who made `mv`, always 0 for MiniMax
+    pub wins: u64,
+    pub losses: u64,
+    /// Heuristic score of `mv` from the move generator
+    pub score: i32,
+    /// Value of `mv` for the player who made it: the mean result for
+    /// MonteCarlo, the negamax score for MiniMax
+    pub value: f64,
+    /// Whether the node is on the principal variation
+    pub pv: bool,
+    pub children: Vec<ExportNode>,
+}
+
+impl ExportNode {
+    // Drop the nodes deeper than `max_depth` or with too few visits
+    fn prune(&mut self, options: &ExportOptions, depth: usize) {
+        if depth >= options.max_depth {
+            self.children.clear();
+            return;
+        }
+        self.children.retain(|c| c.visits >= options.min_visits);
+        for c in self.children.iter_mut() {
+            c.prune(options, depth + 1);
+        }
+    }
+
+    /// Number of nodes in this subtree
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(|c| c.size()).sum::<usize>()
+    }
+
+    /// Moves of the principal variation below this node
+    pub fn pv_moves(&self) -> Vec<(usize, usize)> {
+        let mut moves = vec![];
+        let mut cur = self;
+        while let Some(next) = cur.children.iter().find(|c| c.pv) {
+            moves.push(next.mv.unwrap());
+            cur = next;
+        }
+        moves
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchTreeExport {
+    /// Algorithm which built the tree
+    pub engine: String,
+    /// Root position in the `Board::to_string` format
+    pub board: String,
+    pub width: usize,
+    pub height: usize,
+    pub root: ExportNode,
+}
+
+impl SearchTreeExport {
+    pub fn new(
+        engine: &str,
+        board: String,
+        width: usize,
+        height: usize,
+        mut root: ExportNode,
+        options: &ExportOptions,
+    ) -> Self {
+        root.prune(options, 0);
+        Self {
+            engine: engine.to_string(),
+            board,
+            width,
+            height,
+            root,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Graphviz graph with one box per node, the principal variation is highlighted
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph search {{").unwrap();
+        writeln!(
+            out,
+            "  label=\"{} {}x{}\";",
+            self.engine, self.width, self.height
+        )
+        .unwrap();
+        writeln!(out, "  node [shape=box, fontname=\"monospace\"];").unwrap();
+        let mut next_id = 0;
+        write_dot_node(&mut out, &self.root, &mut next_id);
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    /// Write the tree to `path`, as JSON if it ends with `.json` and as DOT otherwise
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let content = if path.ends_with(".json") {
+            self.to_json()
+        } else {
+            self.to_dot()
+        };
+        fs::write(path, content)
+    }
+}
+
+// Write `node` and its subtree, returns the id of `node`
+fn write_dot_node(out: &mut String, node: &ExportNode, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let name = match node.mv {
+        Some((row, col)) => format!("({}, {})", row, col),
+        None => "root".to_string(),
+    };
+    let style = if node.pv {
+        ", style=filled, fillcolor=lightblue"
+    } else {
+        ""
+    };
+    writeln!(
+        out,
+        "  n{} [label=\"{}\\nvisits {}  w/l {}/{}\\nscore {}  value {:.3}\"{}];",
+        id, name, node.visits, node.wins, node.losses, node.score, node.value, style
+    )
+    .unwrap();
+    for c in node.children.iter() {
+        let child = write_dot_node(out, c, next_id);
+        let edge = if c.pv { " [penwidth=3]" } else { "" };
+        writeln!(out, "  n{} -> n{}{};", id, child, edge).unwrap();
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(mv: (usize, usize), visits: u64, pv: bool) -> ExportNode {
+        ExportNode {
+            mv: Some(mv),
+            visits,
+            pv,
+            ..ExportNode::default()
+        }
+    }
+
+    #[test]
+    fn test_export_prune_and_formats() {
+        let mut child = leaf((7, 7), 10, true);
+        child.children = vec![leaf((7, 8), 6, true), leaf((8, 8), 1, false)];
+        let root = ExportNode {
+            visits: 12,
+            to_move: 1,
+            children: vec![child, leaf((0, 0), 2, false)],
+            ..ExportNode::default()
+        };
+        let options = ExportOptions {
+            max_depth: 2,
+            min_visits: 2,
+        };
+        let export = SearchTreeExport::new("test", String::new(), 15, 15, root.clone(), &options);
+        assert_eq!(export.root.size(), 4);
+        assert_eq!(export.root.pv_moves(), vec![(7, 7), (7, 8)]);
+
+        let dot = export.to_dot();
+        assert!(dot.starts_with("digraph search {"));
+        assert_eq!(dot.matches("->").count(), 3);
+        assert_eq!(dot.matches("penwidth").count(), 2);
+
+        let loaded = SearchTreeExport::from_json(&export.to_json()).unwrap();
+        assert_eq!(loaded.root.size(), 4);
+
+        let shallow = ExportOptions {
+            max_depth: 1,
+            min_visits: 1,
+        };
+        let export = SearchTreeExport::new("test", String::new(), 15, 15, root, &shallow);
+        assert_eq!(export.root.size(), 3);
+    }
+}
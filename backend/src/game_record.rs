@@ -1,3 +1,4 @@
+#![allow(dead_code)]
 // 游戏记录模块 - 用于收集训练数据
 use serde::{Deserialize, Serialize};
 use std::fs::OpenOptions;
@@ -21,6 +22,14 @@ pub struct GameRecord {
     pub algo_player1: String, // Player 1 使用的算法
     pub algo_player2: String, // Player 2 使用的算法
     pub timestamp: String,
+    #[serde(default)]
+    pub seed: Option<u64>, // 随机种子，用 SelfPlay::replay 可以重现这局
+    #[serde(default)]
+    pub level_player1: Option<u8>, // Player 1 的难度等级 (1-10)，None 表示全力
+    #[serde(default)]
+    pub level_player2: Option<u8>, // Player 2 的难度等级
+    #[serde(default)]
+    pub threads: Option<usize>, // 每个引擎的搜索线程数，多线程的对局不能重现
 }
 
 impl GameRecord {
@@ -32,6 +41,10 @@ impl GameRecord {
             algo_player1: algo1,
             algo_player2: algo2,
             timestamp: chrono::Utc::now().to_rfc3339(),
+            seed: None,
+            level_player1: None,
+            level_player2: None,
+            threads: None,
         }
     }
 
@@ -44,6 +57,19 @@ impl GameRecord {
         self.winner = winner;
     }
 
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    pub fn set_levels(&mut self, level1: Option<u8>, level2: Option<u8>) {
+        self.level_player1 = level1;
+        self.level_player2 = level2;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = Some(threads);
+    }
+
     /// 保存游戏记录到 JSON 文件
     pub fn save_to_file(&self, filename: &str) -> std::io::Result<()> {
         let json = serde_json::to_string_pretty(self)?;
@@ -125,6 +151,20 @@ impl DatasetManager {
         self.games.push(game);
     }
 
+    pub fn games(&self) -> &[GameRecord] {
+        &self.games
+    }
+
+    /// 读取 `save_to_file` 追加写入的 JSON 游戏记录
+    pub fn load_from_file(json_file: &str) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(json_file)?;
+        let mut dataset = Self::new();
+        for game in serde_json::Deserializer::from_str(&content).into_iter::<GameRecord>() {
+            dataset.add_game(game?);
+        }
+        Ok(dataset)
+    }
+
     /// 保存整个数据集
     pub fn save_dataset(&self, json_file: &str, csv_file: &str) -> std::io::Result<()> {
         for game in &self.games {
@@ -237,6 +277,22 @@ mod tests {
         assert_eq!(record.winner, Some(1));
     }
 
+    #[test]
+    fn test_load_from_file() {
+        let path = std::env::temp_dir().join("gomoku_test_load_from_file.json");
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+        let mut record = GameRecord::new("minimax".to_string(), "monte_carlo".to_string());
+        record.set_winner(Some(2));
+        record.save_to_file(path).unwrap();
+        record.save_to_file(path).unwrap();
+
+        let dataset = DatasetManager::load_from_file(path).unwrap();
+        assert_eq!(dataset.games().len(), 2);
+        assert_eq!(dataset.games()[1].winner, Some(2));
+        std::fs::remove_file(path).ok();
+    }
+
     #[test]
     fn test_calculate_reward() {
         let mut record = GameRecord::new("test".to_string(), "test".to_string());
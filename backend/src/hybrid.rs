@@ -0,0 +1,150 @@
+#![allow(dead_code)]
+// Hybrid engine - tactics first, then the main search
+use super::algo::Engine;
+use super::board::*;
+use super::search::{SearchControl, SearchLimits, SearchResult, SearchStats, StopToken, Timer};
+use super::threats::{winning_cells, ThreatSolver};
+use super::utils::*;
+
+// Part of the time budget the threat search may use
+const THREAT_TIME_SHARE: u64 = 4;
+
+/* Answers the tactical positions itself and leaves the rest to a main engine.
+ * A move is looked for in this order: a win at once, a block of the opponent's
+ * five, a forced win by continuous fours. Only when none exists the main
+ * engine searches, with the time left over.
+ */
+pub struct HybridEngine {
+    name: String,
+    main: Box<dyn Engine>,
+    solver: ThreatSolver,
+    board: Board,
+    control: SearchControl,
+}
+
+impl HybridEngine {
+    pub fn new(name: &str, main: Box<dyn Engine>) -> Self {
+        let mut engine = Self {
+            name: name.to_string(),
+            main,
+            solver: ThreatSolver::default(),
+            board: Board::new_default(),
+            control: SearchControl::default(),
+        };
+        engine.set_control(SearchControl::default());
+        engine
+    }
+
+    // The move forced by the position and the number of fours to the win, if there is one
+    fn forced_move(&mut self) -> Option<(Move, usize)> {
+        let player = self.board.next_player();
+        if let Some(&(r, c)) = winning_cells(&self.board, player).first() {
+            return Some((Move::new(r, c, 100000, 0), 0));
+        }
+        if let Some(&(r, c)) = winning_cells(&self.board, cfg::opponent(player)).first() {
+            return Some((Move::new(r, c, 0, 0), 0));
+        }
+        let line = self.solver.find_vcf(&self.board, player)?;
+        let (r, c) = line[0];
+        Some((Move::new(r, c, 100000, 0), line.len()))
+    }
+}
+
+impl Engine for HybridEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) {
+        self.board = Board::new_default();
+        self.main.new_game();
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+        self.main.set_position(board);
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.solver.set_control(control.clone());
+        self.main.set_control(control.clone());
+        self.control = control;
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.control.stop.clone()
+    }
+
+    fn predicted_reply(&self) -> Option<Move> {
+        self.main.predicted_reply()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let timer = Timer::start();
+        self.solver
+            .set_time_limit(limits.time_ms.map(|t| t / THREAT_TIME_SHARE));
+        if let Some((best_move, depth)) = self.forced_move() {
+            let nodes = self.solver.nodes();
+            return SearchResult {
+                best_move,
+                stats: SearchStats {
+                    depth: depth as i32,
+                    nodes,
+                    evaluations: nodes,
+                    elapsed_ms: timer.elapsed_ms(),
+                    threads: 1,
+                    ..SearchStats::default()
+                },
+                limits: *limits,
+            };
+        }
+        let main_limits = SearchLimits {
+            time_ms: limits
+                .time_ms
+                .map(|t| t.saturating_sub(timer.elapsed_ms()).max(1)),
+            ..*limits
+        };
+        let mut res = self.main.search(&main_limits);
+        res.stats.nodes += self.solver.nodes();
+        res.stats.evaluations += self.solver.nodes();
+        res.stats.elapsed_ms = timer.elapsed_ms();
+        res.limits.time_ms = limits.time_ms;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algo::{create_engine, EngineOptions};
+    use crate::threats::tests::vcf_board;
+
+    #[test]
+    fn test_hybrid_engine() {
+        let options = EngineOptions::default();
+        let mut engine = create_engine("hybrid", &options).unwrap();
+        let limits = SearchLimits::nodes(100);
+
+        // Row 2, row 5 and column 5 give a win by two fours
+        let mut board = vcf_board();
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        assert!([(2, 5), (5, 5)].contains(&(res.best_move.x, res.best_move.y)));
+        assert_eq!(res.stats.depth, 2);
+
+        // The opponent's four is blocked before anything else
+        board.place(14, 11, 2);
+        board.place(0, 14, 1);
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        assert_eq!((res.best_move.x, res.best_move.y), (14, 13));
+
+        // A quiet position is left to the main engine
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        engine.new_game();
+        engine.set_position(&board);
+        let res = engine.search(&limits);
+        assert!(res.stats.simulations > 0);
+    }
+}
@@ -1,18 +1,107 @@
 // The wasm-pack uses wasm-bindgen to build and generate JavaScript binding file.
 // Import the wasm-bindgen crate.
+use algo::EngineOptions;
+use search::{SearchControl, SearchProgress, StopToken};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 pub mod algo;
+pub mod baseline;
 pub mod board;
+pub mod book;
 mod control;
+pub mod difficulty;
+pub mod export;
 pub mod game_record;
+pub mod hybrid;
 pub mod minimax;
 pub mod monte;
+pub mod pbrain;
+pub mod piskvork;
+pub mod play;
+pub mod policy;
+pub mod ponder;
+pub mod rng;
+pub mod rpc;
+pub mod search;
 pub mod self_play;
+pub mod solver;
+pub mod threats;
 pub mod utils;
 
+/// `options` is an optional JSON encoded `algo::EngineOptions`, e.g. `{"time_ms": 2000}`
 #[wasm_bindgen]
-pub fn gomoku_solve(input: String, algo_type: String, width: usize, height: usize) -> String {
-    let board = input.clone();
-    let res = control::solve_it(&board, &algo_type, width, height);
-    res.into()
+pub fn gomoku_solve(
+    input: String,
+    algo_type: String,
+    width: usize,
+    height: usize,
+    options: Option<String>,
+) -> String {
+    let options = match parse_request(&algo_type, options) {
+        Ok(options) => options,
+        Err(e) => return e,
+    };
+    let control = SearchControl::default();
+    control::solve_it_with(&input, &algo_type, width, height, &options, &control)
+}
+
+// Options given by JS, an error message for the response when they do not parse
+// or an external brain is asked for, which would run a program
+fn parse_request(algo_type: &str, options: Option<String>) -> Result<EngineOptions, String> {
+    if algo::is_external(algo_type) {
+        return Err(control::error_message("external engines are not available"));
+    }
+    parse_options(options)
+}
+
+fn parse_options(options: Option<String>) -> Result<EngineOptions, String> {
+    match options {
+        Some(json) => serde_json::from_str::<EngineOptions>(&json)
+            .map(|options| options.capped())
+            .map_err(|e| control::error_message(&format!("invalid options: {}", e))),
+        None => Ok(EngineOptions::default()),
+    }
+}
+
+/// The engines accepted as `algo_type`, a JSON list of `algo::EngineInfo`
+#[wasm_bindgen]
+pub fn list_engines() -> String {
+    control::engines_json()
+}
+
+// JS values never leave the single wasm thread, the search only needs the wrapper to be Send
+struct JsProgress(js_sys::Function);
+unsafe impl Send for JsProgress {}
+unsafe impl Sync for JsProgress {}
+
+/// Like `gomoku_solve`, but calls `progress` with a JSON encoded `SearchProgress`
+/// while searching. Returning `false` from the callback stops the search.
+/// `options` are the same as for `gomoku_solve`.
+#[wasm_bindgen]
+pub fn gomoku_solve_with_progress(
+    input: String,
+    algo_type: String,
+    width: usize,
+    height: usize,
+    progress: js_sys::Function,
+    options: Option<String>,
+) -> String {
+    let options = match parse_request(&algo_type, options) {
+        Ok(options) => options,
+        Err(e) => return e,
+    };
+    let stop = StopToken::new();
+    let callback = JsProgress(progress);
+    let token = stop.clone();
+    let control = SearchControl::new(
+        stop,
+        Some(Arc::new(move |p: &SearchProgress| {
+            let json = serde_json::to_string(p).unwrap();
+            let res = callback.0.call1(&JsValue::NULL, &JsValue::from_str(&json));
+            if matches!(res, Ok(v) if v == JsValue::FALSE) {
+                token.stop();
+            }
+        })),
+    );
+    control::solve_it_with(&input, &algo_type, width, height, &options, &control)
 }
@@ -1,12 +1,31 @@
+use algo::EngineOptions;
+use board::Board;
 use clap::clap_app;
+use search::SearchControl;
 use std::env;
+use utils::{BOARD_HEIGHT, BOARD_WIDTH};
 mod algo;
+mod baseline;
 mod board;
+mod book;
 mod control;
+mod difficulty;
+mod export;
+mod game_record;
+mod hybrid;
 mod minimax;
 mod monte;
+mod piskvork;
+mod play;
+mod policy;
+mod ponder;
+mod rng;
+mod rpc;
+mod search;
 #[cfg(feature = "server")]
 mod server;
+mod solver;
+mod threats;
 mod utils;
 
 fn main() {
@@ -23,16 +42,86 @@ fn main() {
         (@arg other_self_battle: -o --other_self_battle "Run in other battle self mode")
         (@arg width: -w --width +takes_value "The board width")
         (@arg height: -h --height +takes_value "The board height")
+        (@arg algo: -a --algo +takes_value "The engine to search with, see --engines")
         (@arg depth: -d --depth +takes_value "The search depth for algo")
+        (@arg nodes: -n --nodes +takes_value "The simulations for monte carlo, positions for minimax")
+        (@arg time: -t --time +takes_value "The thinking time in milliseconds")
+        (@arg threads: --threads +takes_value "The search threads")
+        (@arg seed: --seed +takes_value "The seed of the random numbers")
+        (@arg level: -l --level +takes_value "The difficulty level from 1 to 10, full strength by default")
         (@arg server: -s --server "Run in Server mode")
+        (@arg engines: --engines "List the available engines")
+        (@arg jsonrpc: --jsonrpc "Answer JSON-RPC 2.0 requests on stdin, one per line")
+        (@subcommand play =>
+            (about: "Play against an engine in the terminal")
+            (@arg algo: -a --algo +takes_value "The engine to play against, see --engines")
+            (@arg color: -c --color +takes_value "Your stones, x moves first and o second")
+            (@arg width: --width +takes_value "The board width")
+            (@arg height: --height +takes_value "The board height")
+            (@arg rule: --rule +takes_value "freestyle, or standard where only exactly five wins")
+            (@arg depth: -d --depth +takes_value "The search depth for algo")
+            (@arg nodes: -n --nodes +takes_value "The simulations for monte carlo, positions for minimax")
+            (@arg time: -t --time +takes_value "The thinking time in milliseconds")
+            (@arg threads: --threads +takes_value "The search threads")
+            (@arg level: -l --level +takes_value "The difficulty level from 1 to 10, full strength by default")
+            (@arg ponder: --ponder "Let the engine think while it is your turn")
+        )
     )
     .get_matches();
 
-    let mut search_depth = 14;
-    let mut board_width = 4;
-    let mut board_height = 4;
+    let mut board_width = BOARD_WIDTH;
+    let mut board_height = BOARD_HEIGHT;
+    if let Some(width) = matches.value_of("width") {
+        board_width = width.parse::<usize>().unwrap();
+    }
+    if let Some(height) = matches.value_of("height") {
+        board_height = height.parse::<usize>().unwrap();
+    }
+    let options = EngineOptions {
+        threads: matches.value_of("threads").map(|v| v.parse().unwrap()),
+        seed: matches.value_of("seed").map(|v| v.parse().unwrap()),
+        depth: matches.value_of("depth").map(|v| v.parse().unwrap()),
+        nodes: matches.value_of("nodes").map(|v| v.parse().unwrap()),
+        time_ms: matches.value_of("time").map(|v| v.parse().unwrap()),
+        level: matches.value_of("level").map(|v| v.parse().unwrap()),
+    };
+    let algo_type = matches.value_of("algo").unwrap_or("minimax");
 
-    if matches.occurrences_of("battle") > 0 {
+    if let Some(play) = matches.subcommand_matches("play") {
+        let config = play::PlayConfig {
+            engine: play.value_of("algo").unwrap_or("hybrid").to_string(),
+            options: EngineOptions {
+                threads: play.value_of("threads").map(|v| v.parse().unwrap()),
+                depth: play.value_of("depth").map(|v| v.parse().unwrap()),
+                nodes: play.value_of("nodes").map(|v| v.parse().unwrap()),
+                time_ms: play.value_of("time").map(|v| v.parse().unwrap()),
+                level: play.value_of("level").map(|v| v.parse().unwrap()),
+                ..EngineOptions::default()
+            },
+            human: match play.value_of("color").unwrap_or("x") {
+                "x" | "X" => 1,
+                "o" | "O" => 2,
+                other => panic!("Unknown color {}, use x or o", other),
+            },
+            width: play
+                .value_of("width")
+                .map_or(BOARD_WIDTH, |v| v.parse().unwrap()),
+            height: play
+                .value_of("height")
+                .map_or(BOARD_HEIGHT, |v| v.parse().unwrap()),
+            exact_five: match play.value_of("rule").unwrap_or("freestyle") {
+                "freestyle" => false,
+                "standard" => true,
+                other => panic!("Unknown rule {}, use freestyle or standard", other),
+            },
+            ponder: play.occurrences_of("ponder") > 0,
+        };
+        play::run(&config);
+    } else if matches.occurrences_of("engines") > 0 {
+        for engine in algo::list_engines() {
+            println!("{:<20} {}", engine.name, engine.description);
+        }
+    } else if matches.occurrences_of("battle") > 0 {
         control::battle();
     } else if matches.occurrences_of("monte_battle") > 0 {
         control::battle_monte();
@@ -42,6 +131,13 @@ fn main() {
         control::battle_self();
     } else if matches.occurrences_of("other_self_battle") > 0 {
         control::battle_other_self();
+    } else if matches.occurrences_of("jsonrpc") > 0 {
+        let board = Board::new(
+            "0".repeat(board_width * board_height),
+            board_width,
+            board_height,
+        );
+        rpc::run_stdio(algo_type, &options, board);
     } else if matches.occurrences_of("server") > 0 {
         let port_key = "FUNCTIONS_CUSTOMHANDLER_PORT";
         let _port: u16 = match env::var(port_key) {
@@ -55,21 +151,18 @@ fn main() {
                 panic!("no build with feature : server")
             }
         };
+    } else if let Some(input) = matches.value_of("input") {
+        let control = SearchControl::default();
+        let res = control::solve_it_with(
+            input,
+            algo_type,
+            board_width,
+            board_height,
+            &options,
+            &control,
+        );
+        println!("{}", res);
     } else {
-        if let Some(depth) = matches.value_of("depth") {
-            search_depth = depth.parse::<i32>().unwrap();
-        }
-        if let Some(width) = matches.value_of("width") {
-            board_width = width.parse::<usize>().unwrap();
-        }
-        if let Some(height) = matches.value_of("height") {
-            board_height = height.parse::<usize>().unwrap();
-        }
-        if let Some(input) = matches.value_of("input") {
-            let board = board::Board::new(input.to_string(), board_width, board_height);
-            print!("created board: {:?} with depth: {}", board, search_depth);
-        } else {
-            panic!("Input board is required");
-        }
+        panic!("Input board is required");
     }
 }
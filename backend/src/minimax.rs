@@ -1,35 +1,280 @@
 #![allow(dead_code)]
 use super::board::*;
 use super::utils::*;
-use crate::algo::GomokuSolver;
+use crate::algo::{Engine, EngineOptions};
+use crate::export::{ExportNode, ExportOptions, SearchTreeExport};
+use crate::search::{
+    SearchControl, SearchLimits, SearchProgress, SearchResult, SearchStats, StopToken, Timer,
+};
+use rayon::prelude::*;
 use std::cmp::*;
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, Mutex};
+
+// Upper bound on stored positions, a shard is emptied when it gets its share
+const TT_CAPACITY: usize = 1 << 19;
+// Independently locked parts of the table, so the workers rarely wait for each other
+const TT_SHARDS: usize = 16;
+
+// A position: the stones, the side to move, the board size and the winning rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TtKey {
+    hash: u64,
+    player: u8,
+    rules: (usize, usize, usize, bool),
+}
+
+impl TtKey {
+    fn new(board: &Board, player: u8) -> Self {
+        Self {
+            hash: board.hash(),
+            player,
+            rules: (board.width, board.height, board.win_len, board.exact_five),
+        }
+    }
+}
+
+// How the stored score relates to the real one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    // The search failed high, the real score is at least this
+    Lower,
+    // The search failed low, the real score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TtEntry {
+    depth: i32,
+    score: i32,
+    row: usize,
+    col: usize,
+    bound: Bound,
+}
+
+// The deepest search of a position and the latest shallower one
+type TtMap = HashMap<TtKey, [Option<TtEntry>; 2]>;
+
+/* Results of finished searches, shared by all workers of a `MiniMax`.
+ * An entry only stands for a search of the same depth, as the scores add up
+ * the moves of both sides and differ from one depth to the next. Keeping two
+ * depths per position lets every iteration of the next search find its own.
+ * A bound answers a search whose window it falls outside of.
+ */
+#[derive(Clone)]
+pub struct TranspositionTable(Arc<Vec<Mutex<TtMap>>>);
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self(Arc::new(
+            (0..TT_SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        ))
+    }
+}
+
+impl TranspositionTable {
+    fn shard(&self, key: &TtKey) -> &Mutex<TtMap> {
+        &self.0[key.hash as usize % TT_SHARDS]
+    }
+
+    // The stored result of searching `key` to `depth` with the window `alpha..beta`
+    fn probe(&self, key: &TtKey, depth: i32, alpha: i32, beta: i32) -> Option<(i32, usize, usize)> {
+        let slots = *self.shard(key).lock().unwrap().get(key)?;
+        let entry = slots.iter().flatten().find(|e| e.depth == depth)?;
+        let usable = match entry.bound {
+            Bound::Exact => true,
+            Bound::Lower => entry.score >= beta,
+            Bound::Upper => entry.score <= alpha,
+        };
+        if usable {
+            Some((entry.score, entry.row, entry.col))
+        } else {
+            None
+        }
+    }
+
+    fn insert(&self, key: TtKey, entry: TtEntry) {
+        let mut shard = self.shard(&key).lock().unwrap();
+        if shard.len() >= TT_CAPACITY / TT_SHARDS {
+            shard.clear();
+        }
+        let slots = shard.entry(key).or_default();
+        match slots[0] {
+            Some(deepest) if deepest.depth > entry.depth => slots[1] = Some(entry),
+            Some(deepest) if deepest.depth == entry.depth => slots[0] = Some(entry),
+            deepest => *slots = [Some(entry), deepest],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        for shard in self.0.iter() {
+            shard.lock().unwrap().clear();
+        }
+    }
+}
+
+// Moves searched by the current iteration, kept for `MiniMax::export_tree`
+#[derive(Default)]
+struct TreeRecorder {
+    // Levels below the root which are recorded, 0 when recording is off
+    max_depth: usize,
+    // Path from the root to the node being searched
+    stack: Vec<ExportNode>,
+    // Root position and tree of the last finished iteration
+    board: Option<Board>,
+    last: Option<ExportNode>,
+}
 
 pub struct MiniMax {
     player: u8,
     depth: i32,
+    root_depth: i32,
+    threads: usize,
+    // Workers of the root moves, built once with the searcher when it has several threads
+    pool: Option<rayon::ThreadPool>,
+    control: SearchControl,
+    limits: SearchLimits,
+    timer: Timer,
+    // `eval_node` when the last `search` started, the node limit counts from there
+    start_nodes: u32,
+    // Deepest iteration finished by the last `search`
+    completed_depth: i32,
+    tt: TranspositionTable,
+    recorder: TreeRecorder,
     pub gen_move_count: u32,
     pub eval_node: u32,
+    pub tt_hits: u32,
     debug: bool,
 }
 
-impl GomokuSolver for MiniMax {
-    fn best_move(input: &str, width: usize, height: usize) -> Move {
-        let mut board = Board::new(input.to_string(), width, height);
+// Depth searched when the limits do not say otherwise
+const DEFAULT_DEPTH: i32 = 5;
+
+/// The "minimax" engine, the transposition table is kept for the whole game
+pub struct MiniMaxEngine {
+    runner: MiniMax,
+    board: Board,
+}
+
+impl MiniMaxEngine {
+    pub fn new(options: &EngineOptions) -> Self {
+        let threads = options.threads();
+        Self {
+            runner: MiniMax::new_with_threads(1, DEFAULT_DEPTH, threads),
+            board: Board::new_default(),
+        }
+    }
+}
+
+impl Engine for MiniMaxEngine {
+    fn name(&self) -> &str {
+        "minimax"
+    }
+
+    fn new_game(&mut self) {
+        self.runner.clear_transposition_table();
+        self.board = Board::new_default();
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.runner.set_control(control);
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.runner.control.stop.clone()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let mut board = self.board.clone();
         let player = board.next_player();
-        let mut runner = MiniMax::new(player, 5);
-        let (s, r, c) = runner.run_heuristic(&mut board, player);
-        Move::new(r, c, s, 0)
+        self.runner.player = player;
+        self.runner.depth = limits.depth.unwrap_or(DEFAULT_DEPTH);
+        self.runner.set_limits(*limits);
+        let nodes = self.runner.eval_node;
+        let evaluations = self.runner.gen_move_count;
+        let tt_hits = self.runner.tt_hits;
+        let (s, r, c) = self.runner.search(&mut board, player);
+        let stats = SearchStats {
+            depth: self.runner.completed_depth,
+            nodes: (self.runner.eval_node - nodes) as u64,
+            evaluations: (self.runner.gen_move_count - evaluations) as u64,
+            simulations: 0,
+            tt_hits: (self.runner.tt_hits - tt_hits) as u64,
+            elapsed_ms: self.runner.timer.elapsed_ms(),
+            threads: self.runner.threads,
+        };
+        let limits = SearchLimits {
+            depth: Some(self.runner.depth),
+            ..*limits
+        };
+        // Without candidates the search answers (0, 0), which need not be empty
+        if board.get(r as i32, c as i32) != Some(0) {
+            return SearchResult::no_move(stats, limits);
+        }
+        SearchResult {
+            best_move: Move::new(r, c, s, 0),
+            stats,
+            limits,
+        }
+    }
+}
+
+/// Number of threads used by the "minimax" and "monte_carlo" engines, taken from `GOMOKU_THREADS`.
+/// `0` means one thread per cpu, unset means single-threaded search.
+pub fn search_threads() -> usize {
+    match env::var("GOMOKU_THREADS").map(|v| v.parse::<usize>()) {
+        Ok(Ok(0)) => num_cpus::get(),
+        Ok(Ok(n)) => n,
+        _ => 1,
     }
 }
 
 impl MiniMax {
     pub fn new(player: u8, depth: i32) -> Self {
+        Self::new_with_threads(player, depth, 1)
+    }
+
+    /// Create a searcher which splits the root moves over `threads` workers.
+    /// When the threads can not be started it searches on its own.
+    pub fn new_with_threads(player: u8, depth: i32, threads: usize) -> Self {
+        let pool = if threads > 1 {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .ok()
+        } else {
+            None
+        };
         Self {
             player: player,
             depth: depth,
+            root_depth: depth,
+            threads: pool.as_ref().map_or(1, |p| p.current_num_threads()),
+            pool,
+            control: SearchControl::default(),
+            limits: SearchLimits::default(),
+            timer: Timer::start(),
+            start_nodes: 0,
+            completed_depth: 0,
+            tt: TranspositionTable::default(),
+            recorder: TreeRecorder::default(),
             gen_move_count: 0,
             eval_node: 0,
+            tt_hits: 0,
             debug: match env::var("GOMOKU_DEBUG") {
                 Ok(_) => true,
                 _ => false,
@@ -37,6 +282,90 @@ impl MiniMax {
         }
     }
 
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Attach a stop token and progress callback, checked by `search` and `run_heuristic`
+    pub fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    /// Time and node budget of the next searches, the search ends like a stopped one
+    /// when it runs out. The default has no limits.
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    // Whether the search was stopped or used up its limits
+    fn should_stop(&self) -> bool {
+        self.control.is_stopped()
+            || self
+                .limits
+                .time_ms
+                .is_some_and(|t| self.timer.elapsed_ms() >= t)
+            || self
+                .limits
+                .nodes
+                .is_some_and(|n| (self.eval_node - self.start_nodes) as u64 >= n)
+    }
+
+    /// The transposition table is kept between searches until it is cleared
+    pub fn transposition_table(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    pub fn clear_transposition_table(&self) {
+        self.tt.clear();
+    }
+
+    /// Record the moves searched down to `max_depth` levels below the root, so
+    /// the last finished iteration can be looked at with `export_tree`. 0 stops recording.
+    pub fn record_tree(&mut self, max_depth: usize) {
+        self.recorder.max_depth = max_depth;
+    }
+
+    /// The tree recorded by the last finished iteration. Moves answered from the
+    /// transposition table or searched by other threads have no children.
+    pub fn export_tree(&self, options: &ExportOptions) -> Option<SearchTreeExport> {
+        let board = self.recorder.board.as_ref()?;
+        let root = self.recorder.last.clone()?;
+        Some(SearchTreeExport::new(
+            "minimax",
+            board.to_string(),
+            board.width,
+            board.height,
+            root,
+            options,
+        ))
+    }
+
+    fn start_recording(&mut self, player: u8) {
+        if self.recorder.max_depth > 0 {
+            self.recorder.stack = vec![ExportNode {
+                to_move: player,
+                ..ExportNode::default()
+            }];
+        }
+    }
+
+    // Keep the tree of a finished iteration which chose `res`
+    fn finish_recording(&mut self, board: &Board, res: (i32, usize, usize), nodes: u32) {
+        if let Some(mut root) = self.recorder.stack.pop() {
+            root.visits = nodes as u64;
+            root.value = res.0 as f64;
+            mark_pv(&mut root, Some((res.1, res.2)));
+            self.recorder.board = Some(board.clone());
+            self.recorder.last = Some(root);
+        }
+        self.recorder.stack.clear();
+    }
+
+    fn is_recording(&self, depth: i32) -> bool {
+        !self.recorder.stack.is_empty()
+            && ((self.root_depth - depth) as usize) < self.recorder.max_depth
+    }
+
     // The naive minimax algorithm
     pub fn gen_move(&mut self, board: &mut Board, player: u8, depth: i32) -> (i32, usize, usize) {
         self.eval_node += 1;
@@ -68,13 +397,54 @@ impl MiniMax {
     }
 
     pub fn run_heuristic(&mut self, board: &mut Board, player: u8) -> (i32, usize, usize) {
-        self.gen_move_heuristic(
+        self.timer = Timer::start();
+        self.root_depth = self.depth;
+        let nodes = self.eval_node;
+        self.start_recording(player);
+        let res = self.gen_move_heuristic(
             board,
             player,
             self.depth,
             std::i32::MIN / 2,
             std::i32::MAX / 2,
-        )
+        );
+        self.finish_recording(board, res, self.eval_node - nodes);
+        res
+    }
+
+    /* Iterative deepening up to `self.depth`.
+     * Progress is reported after every finished depth, and when the search is
+     * stopped the result of the last finished depth is returned.
+     */
+    pub fn search(&mut self, board: &mut Board, player: u8) -> (i32, usize, usize) {
+        let mut result = None;
+        self.timer = Timer::start();
+        self.start_nodes = self.eval_node;
+        self.completed_depth = 0;
+        for depth in 1..=self.depth {
+            self.root_depth = depth;
+            let nodes = self.eval_node;
+            self.start_recording(player);
+            let res = self.gen_move_heuristic(board, player, depth, i32::MIN / 2, i32::MAX / 2);
+            if self.should_stop() && result.is_some() {
+                self.recorder.stack.clear();
+                break;
+            }
+            self.finish_recording(board, res, self.eval_node - nodes);
+            result = Some(res);
+            self.completed_depth = depth;
+            self.control.report(SearchProgress {
+                depth,
+                nodes: self.eval_node as u64,
+                best_move: Some((res.1, res.2)),
+                score: res.0,
+            });
+            if self.should_stop() {
+                break;
+            }
+        }
+        self.root_depth = self.depth;
+        result.unwrap_or((0, 0, 0))
     }
     /* The minimax algorithm with alpha-beta tunning
      * In Negamax implmentation
@@ -88,6 +458,15 @@ impl MiniMax {
         beta: i32,
     ) -> (i32, usize, usize) {
         self.eval_node += 1;
+        // The root is always searched, its move may be replaced by a block below
+        let key = TtKey::new(board, player);
+        let is_root = depth == self.root_depth;
+        if !is_root {
+            if let Some(res) = self.tt.probe(&key, depth, alpha, beta) {
+                self.tt_hits += 1;
+                return res;
+            }
+        }
         let mut max_score = std::i32::MIN;
         let mut final_move = Move::new(0, 0, 0, 0);
         let mut cur_alpha = alpha;
@@ -95,6 +474,7 @@ impl MiniMax {
         let mut block_move = None;
         let mut best_moves: Vec<Move> = vec![];
         let mut candidates = board.gen_ordered_moves(player);
+        self.gen_move_count += 1;
         if candidates.len() == 0 {
             return (0, 0, 0);
         }
@@ -103,17 +483,43 @@ impl MiniMax {
         }
 
         let opponent_candidates = board.gen_ordered_moves(cfg::opponent(player));
+        self.gen_move_count += 1;
         // If there are more than 2 threatening choices for opponent, we must lose the game
         // Anyway, try to block the first threatening choice
         if opponent_candidates.len() >= 1 && opponent_candidates[0].is_dead_move() {
             block_move = opponent_candidates.first();
         }
 
+        // Root splitting: every root move is searched with the full window on its own worker
+        let parallel_scores = if depth == self.root_depth && depth > 1 && self.pool.is_some() {
+            Some(self.search_root_parallel(board, player, &candidates, depth, alpha, beta))
+        } else {
+            None
+        };
+
         for i in 0..candidates.len() {
+            // Every node searches at least its first move, so a stopped search still has an answer
+            if i > 0 && self.should_stop() {
+                break;
+            }
             let mut mv = candidates[i];
-            board.place(mv.x, mv.y, player);
             let mut opponent_score = 0;
-            if depth > 1 {
+            let recording = self.is_recording(depth);
+            let nodes = self.eval_node;
+            if recording {
+                self.recorder.stack.push(ExportNode {
+                    mv: Some((mv.x, mv.y)),
+                    to_move: cfg::opponent(player),
+                    score: mv.score,
+                    ..ExportNode::default()
+                });
+            }
+            let mut searched = 0;
+            if let Some(scores) = &parallel_scores {
+                opponent_score = scores[i].0;
+                searched = scores[i].1;
+            } else if depth > 1 {
+                board.place(mv.x, mv.y, player);
                 let (s, _, _) = self.gen_move_heuristic(
                     board,
                     cfg::opponent(player),
@@ -122,13 +528,19 @@ impl MiniMax {
                     -cur_alpha + mv.score,
                 );
                 opponent_score = s;
+                board.place(mv.x, mv.y, 0);
             }
-            board.place(mv.x, mv.y, 0);
-            if depth == self.depth && self.debug {
+            if depth == self.root_depth && self.debug {
                 println!("move: {:?} => oppo_score: {}", mv, opponent_score);
             }
             mv.score -= opponent_score;
             candidates[i].score = mv.score;
+            if recording {
+                let mut node = self.recorder.stack.pop().unwrap();
+                node.visits = max(self.eval_node - nodes + searched, 1) as u64;
+                node.value = mv.score as f64;
+                self.recorder.stack.last_mut().unwrap().children.push(node);
+            }
             if mv.score > max_score {
                 //println!("opponent_score: {}", opponent_score);
                 max_score = mv.score;
@@ -143,7 +555,7 @@ impl MiniMax {
                 break;
             }
         }
-        if depth == self.depth && self.debug {
+        if depth == self.root_depth && self.debug {
             for i in 0..candidates.len() {
                 println!("possible move: {:?}", candidates[i]);
             }
@@ -155,27 +567,110 @@ impl MiniMax {
                 block_move, final_move, depth, self.depth,
             );
         }
-        if depth == self.depth && block_move.is_some() && block_move.unwrap().is_dead_move() {
-            //println!("Use block move: {:?}", block_move);
+        if depth == self.root_depth && block_move.is_some() && block_move.unwrap().is_dead_move() {
             final_move = *block_move.unwrap();
             max_score = final_move.score;
-            println!(
-                "+Block move: {:?} \n {:?} depth:{} self.depth:{}",
-                block_move, final_move, depth, self.depth,
-            );
+            // stdout may be a protocol channel, like pbrain or JSON-RPC
+            if self.debug {
+                println!("Use block move: {:?}", block_move);
+            }
         } else if best_moves.len() > 1 {
             //choose by original score
             best_moves.sort_by(|a, b| b.original_score.cmp(&a.original_score));
             final_move = *(best_moves.first().unwrap());
         }
-        if depth == self.depth && self.debug {
+        if depth == self.root_depth && self.debug {
             println!(
                 "Final move: {:?} depth:{} self.depth:{}, max_score: {}",
                 final_move, depth, self.depth, max_score
             );
         }
 
-        (max_score, final_move.x, final_move.y)
+        let res = (max_score, final_move.x, final_move.y);
+        // A stopped search did not look at every move, so its result is not stored
+        if !is_root && !self.should_stop() {
+            let bound = if max_score <= alpha {
+                Bound::Upper
+            } else if max_score >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.tt.insert(
+                key,
+                TtEntry {
+                    depth,
+                    score: max_score,
+                    row: final_move.x,
+                    col: final_move.y,
+                    bound,
+                },
+            );
+        }
+        res
+    }
+
+    fn search_root_parallel(
+        &mut self,
+        board: &Board,
+        player: u8,
+        candidates: &[Move],
+        depth: i32,
+        alpha: i32,
+        beta: i32,
+    ) -> Vec<(i32, u32)> {
+        let pool = self.pool.as_ref().unwrap();
+        // The workers share what is left of the node budget, each its part
+        let nodes = self.limits.nodes.map(|n| {
+            let left = n.saturating_sub((self.eval_node - self.start_nodes) as u64);
+            max(left / candidates.len() as u64, 1)
+        });
+        let results: Vec<(i32, u32, u32, u32)> = pool.install(|| {
+            candidates
+                .par_iter()
+                .map(|mv| {
+                    let mut board = board.clone();
+                    let mut worker = MiniMax::new(self.player, self.depth);
+                    worker.set_control(SearchControl::new(self.control.stop.clone(), None));
+                    worker.timer = self.timer;
+                    worker.limits = SearchLimits {
+                        nodes,
+                        ..self.limits
+                    };
+                    worker.tt = self.tt.clone();
+                    board.place(mv.x, mv.y, player);
+                    let (s, _, _) = worker.gen_move_heuristic(
+                        &mut board,
+                        cfg::opponent(player),
+                        depth - 1,
+                        -beta,
+                        -alpha + mv.score,
+                    );
+                    (s, worker.eval_node, worker.tt_hits, worker.gen_move_count)
+                })
+                .collect()
+        });
+        self.eval_node += results.iter().map(|(_, n, _, _)| n).sum::<u32>();
+        self.tt_hits += results.iter().map(|(_, _, h, _)| h).sum::<u32>();
+        self.gen_move_count += results.iter().map(|(_, _, _, e)| e).sum::<u32>();
+        results.into_iter().map(|(s, n, _, _)| (s, n)).collect()
+    }
+}
+
+// Mark the principal variation: `best` at the root, the best valued child below it
+fn mark_pv(node: &mut ExportNode, best: Option<(usize, usize)>) {
+    node.pv = true;
+    let next = match best {
+        Some(mv) => node.children.iter().position(|c| c.mv == Some(mv)),
+        None => (0..node.children.len()).max_by(|a, b| {
+            node.children[*a]
+                .value
+                .partial_cmp(&node.children[*b].value)
+                .unwrap()
+        }),
+    };
+    if let Some(i) = next {
+        mark_pv(&mut node.children[i], None);
     }
 }
 
@@ -386,6 +881,194 @@ mod tests {
         assert_eq!(col, 7);
     }
 
+    #[test]
+    fn test_algo_parallel() {
+        let mut board = Board::new(
+            String::from(
+                "
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . + o o . . . . . . .
+                . . . . . + + o . . . . . . .
+                . . . . . . o . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+                . . . . . . . . . . . . . . .
+    ",
+            ),
+            15,
+            15,
+        );
+
+        let mut runner = MiniMax::new_with_threads(2, 4, 4);
+        assert_eq!(runner.threads(), 4);
+        let (_, row, col) = runner.run_heuristic(&mut board, 2);
+        assert_eq!(row, 6);
+        assert_eq!(col, 5);
+        assert!(runner.eval_node > 1);
+        let mut engine = MiniMaxEngine::new(&EngineOptions {
+            threads: Some(4),
+            ..EngineOptions::default()
+        });
+        engine.set_position(&board);
+        assert_eq!(engine.search(&SearchLimits::depth(2)).stats.threads, 4);
+
+        // The root moves share the node budget instead of each using all of it
+        runner.set_limits(SearchLimits::nodes(2000));
+        runner.depth = 8;
+        let nodes = runner.eval_node;
+        let (_, row, col) = runner.search(&mut board, 2);
+        assert_eq!(board.get(row as i32, col as i32), Some(0));
+        assert!(runner.eval_node - nodes > 2000 / 4);
+        assert!(runner.eval_node - nodes < 2000 + 2000 / 4);
+    }
+
+    #[test]
+    fn test_search_progress_and_stop() {
+        use crate::search::StopToken;
+        use std::sync::{Arc, Mutex};
+
+        let mut board = make_empty_board();
+        board.place(7, 7, 1);
+        let depths = Arc::new(Mutex::new(vec![]));
+        let sink = depths.clone();
+        let mut runner = MiniMax::new(2, 3);
+        runner.set_control(SearchControl::new(
+            StopToken::new(),
+            Some(Arc::new(move |p: &SearchProgress| {
+                sink.lock().unwrap().push(p.depth)
+            })),
+        ));
+        runner.search(&mut board, 2);
+        assert_eq!(*depths.lock().unwrap(), vec![1, 2, 3]);
+
+        let stop = StopToken::new();
+        stop.stop();
+        runner.set_control(SearchControl::new(stop, None));
+        let (_, row, col) = runner.search(&mut board, 2);
+        assert_eq!(board.get(row as i32, col as i32), Some(0));
+    }
+
+    #[test]
+    fn test_minimax_engine_no_move() {
+        let full = Board::new("12".repeat(9 * 9 / 2) + "1", 9, 9);
+        for name in ["minimax", "hybrid_minimax"] {
+            let options = EngineOptions {
+                threads: Some(1),
+                ..EngineOptions::default()
+            };
+            let mut engine = crate::algo::create_engine(name, &options).unwrap();
+            engine.set_position(&full);
+            assert!(
+                !engine.search(&SearchLimits::depth(3)).has_move(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_transposition_table_reuse() {
+        let mut board = make_empty_board();
+        board.place(7, 7, 1);
+        board.place(7, 8, 2);
+        board.place(8, 8, 1);
+        let mut runner = MiniMax::new(2, 3);
+        let first = runner.search(&mut board, 2);
+        assert!(!runner.transposition_table().is_empty());
+        let nodes = runner.eval_node;
+
+        // The roots of the 3 iterations are searched again, every move below them is a hit
+        let second = runner.search(&mut board, 2);
+        assert_eq!(first, second);
+        assert!(runner.eval_node - nodes < nodes);
+        assert_eq!(runner.tt_hits, runner.eval_node - nodes - 3);
+
+        runner.clear_transposition_table();
+        assert!(runner.transposition_table().is_empty());
+
+        // The same stones under another rule are searched again
+        runner.search(&mut board, 2);
+        let hits = runner.tt_hits;
+        board.exact_five = true;
+        assert_eq!(
+            runner.search(&mut board, 2),
+            MiniMax::new(2, 3).search(&mut board, 2)
+        );
+        assert_eq!(runner.tt_hits, hits);
+    }
+
+    #[test]
+    fn test_transposition_table_bounds() {
+        let board = make_empty_board();
+        let key = TtKey::new(&board, 1);
+        let tt = TranspositionTable::default();
+        let entry = |score, bound| TtEntry {
+            depth: 2,
+            score,
+            row: 7,
+            col: 7,
+            bound,
+        };
+        tt.insert(key, entry(10, Bound::Lower));
+        assert_eq!(tt.probe(&key, 2, 0, 5), Some((10, 7, 7)));
+        assert_eq!(tt.probe(&key, 2, 0, 20), None);
+        assert_eq!(tt.probe(&key, 3, 0, 5), None);
+        tt.insert(key, entry(10, Bound::Upper));
+        assert_eq!(tt.probe(&key, 2, 15, 20), Some((10, 7, 7)));
+        assert_eq!(tt.probe(&key, 2, 0, 20), None);
+        tt.insert(key, entry(10, Bound::Exact));
+        assert_eq!(tt.probe(&key, 2, 0, 20), Some((10, 7, 7)));
+
+        // A deeper search does not push out the shallower one
+        tt.insert(
+            key,
+            TtEntry {
+                depth: 4,
+                ..entry(30, Bound::Exact)
+            },
+        );
+        assert_eq!(tt.probe(&key, 4, 0, 20), Some((30, 7, 7)));
+        assert_eq!(tt.probe(&key, 2, 0, 20), Some((10, 7, 7)));
+        assert_eq!(tt.len(), 1);
+        assert_eq!(tt.probe(&TtKey::new(&board, 2), 2, 0, 20), None);
+    }
+
+    #[test]
+    fn test_export_tree() {
+        let mut board = make_empty_board();
+        board.place(7, 7, 1);
+        board.place(7, 8, 2);
+        board.place(8, 8, 1);
+        let mut runner = MiniMax::new(2, 3);
+        assert!(runner.export_tree(&ExportOptions::default()).is_none());
+        runner.record_tree(2);
+        let (_, row, col) = runner.search(&mut board, 2);
+
+        let export = runner.export_tree(&ExportOptions::default()).unwrap();
+        let root = &export.root;
+        assert_eq!(root.to_move, 2);
+        assert!(root.children.len() > 1);
+        assert!(root.children.iter().all(|c| c.children.len() <= 20));
+        assert!(root
+            .children
+            .iter()
+            .flat_map(|c| c.children.iter())
+            .all(|g| g.children.is_empty()));
+        // The principal variation starts with the move played
+        let pv = root.pv_moves();
+        assert_eq!(pv.len(), 2);
+        assert_eq!(pv[0], (row, col));
+        assert_eq!(export.board, board.to_string());
+    }
+
     #[allow(unused_assignments)]
     #[test]
     fn test_algo_battle_self() {
@@ -1,16 +1,104 @@
 #![allow(dead_code)]
 use super::algo::*;
 use super::board::*;
+use super::export::{ExportNode, ExportOptions, SearchTreeExport};
+use super::policy::{static_winner, HeuristicPrior, HeuristicRollout, PriorPolicy, RolloutPolicy};
+use super::rng::{random_seed, GameRng};
+use super::search::{
+    SearchControl, SearchLimits, SearchProgress, SearchResult, SearchStats, StopToken, Timer,
+};
 use super::utils::*;
 
-#[cfg(feature = "random")]
-use rand::Rng;
+use rayon::prelude::*;
 use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 type Id = usize;
 
+/// Proven value of a node for the player who made the move leading to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Proof {
+    Unknown,
+    Win,
+    Loss,
+}
+
+/// How `best_child` scores the children of a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    /// UCT on the results of the simulations through the child
+    Uct,
+    /// RAVE: UCT blended with the all-moves-as-first value of the child, the
+    /// AMAF value fades out once the child has about `equivalence` visits
+    Rave { equivalence: f64 },
+    /// PUCT: the exploration term of a move is weighted by its prior from the
+    /// `PriorPolicy`, moves are expanded in order of their priors
+    Puct { c_puct: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonteCarloConfig {
+    pub selection: Selection,
+    /// Exploration constant of the UCT term
+    pub exploration: f64,
+    /// Moves played by a rollout before the position is evaluated statically,
+    /// unless the `RolloutPolicy` has its own depth
+    pub rollout_depth: usize,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        Self {
+            selection: Selection::Uct,
+            exploration: 0.7,
+            rollout_depth: 10,
+        }
+    }
+}
+
+impl MonteCarloConfig {
+    pub fn rave() -> Self {
+        Self {
+            selection: Selection::Rave {
+                equivalence: 1000.0,
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn puct() -> Self {
+        Self {
+            selection: Selection::Puct { c_puct: 1.5 },
+            ..Self::default()
+        }
+    }
+
+    /// Config of the MonteCarlo variant named `algo_type`
+    pub fn for_algo(algo_type: &str) -> Option<Self> {
+        match algo_type {
+            "monte_carlo" => Some(Self::default()),
+            "monte_carlo_rave" => Some(Self::rave()),
+            "monte_carlo_puct" => Some(Self::puct()),
+            _ => None,
+        }
+    }
+}
+
+/* Nodes keep only their move and statistics. The position of a node is rebuilt
+ * by replaying the moves from the root, and the moves of a node are generated
+ * the first time it is expanded, so most leaves never hold a move list.
+ */
 pub struct Tree {
     nodes: Vec<Node>,
+    // Position at the root
+    state: Board,
+    config: MonteCarloConfig,
+    prior: Arc<dyn PriorPolicy>,
+    rollout: Arc<dyn RolloutPolicy>,
+    rng: GameRng,
+    // Positions scored by the move generator or the static evaluation
+    evaluations: u64,
 }
 
 #[derive(Debug)]
@@ -21,46 +109,212 @@ pub struct Node {
     visited_count: u32,
     win_count: u32,
     loss_count: u32,
-    state: Board,
-    untried_moves: Vec<Move>,
+    // All-moves-as-first statistics, updated whenever the move of this node is
+    // played by the same player later in a simulation. Only used by RAVE.
+    amaf_count: u32,
+    amaf_win_count: u32,
+    amaf_loss_count: u32,
+    // `None` until the node is expanded for the first time
+    untried_moves: Option<Vec<Move>>,
+    // Priors of the untried moves with PUCT selection, empty otherwise
+    untried_priors: Vec<f32>,
+    prior: f32,
     player: u8,
+    terminal: bool,
+    proof: Proof,
     action: Option<Move>,
 }
 
 impl Tree {
-    pub fn new() -> Self {
-        Tree { nodes: vec![] }
+    pub fn new(state: Board, player: u8) -> Self {
+        let terminal = state.any_winner().is_some();
+        Tree {
+            nodes: vec![Node::new(0, 0, player, None, terminal)],
+            state,
+            config: MonteCarloConfig::default(),
+            prior: Arc::new(HeuristicPrior::default()),
+            rollout: Arc::new(HeuristicRollout),
+            rng: GameRng::from_entropy(),
+            evaluations: 0,
+        }
     }
 
-    pub fn new_node(
-        &mut self,
-        parent: Id,
-        state: Board,
-        player: u8,
-        mv: Option<Move>,
-    ) -> Option<&Node> {
-        let id = self.nodes.len();
-        self.nodes.push(Node::new(id, parent, state, player, mv));
-        if parent != id {
-            self.nodes[parent].children.push(id);
+    // An empty tree at `state` with the same config and policies, its random
+    // numbers are drawn from this tree's generator
+    fn new_like(&mut self, state: Board, player: u8) -> Self {
+        Tree {
+            config: self.config,
+            prior: self.prior.clone(),
+            rollout: self.rollout.clone(),
+            rng: self.rng.fork(),
+            ..Tree::new(state, player)
         }
-        self.nodes.get(id)
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = GameRng::new(seed);
+    }
+
+    pub fn set_config(&mut self, config: MonteCarloConfig) {
+        self.config = config;
+    }
+
+    pub fn set_prior_policy(&mut self, prior: Arc<dyn PriorPolicy>) {
+        self.prior = prior;
+    }
+
+    pub fn set_rollout_policy(&mut self, rollout: Arc<dyn RolloutPolicy>) {
+        self.rollout = rollout;
+    }
+
+    fn new_node(&mut self, parent: Id, player: u8, mv: Option<Move>, terminal: bool) -> Id {
+        let id = self.nodes.len();
+        self.nodes.push(Node::new(id, parent, player, mv, terminal));
+        self.nodes[parent].children.push(id);
+        id
     }
 
     fn get_node(&self, id: Id) -> Option<&Node> {
         self.nodes.get(id)
     }
 
-    fn expand(&mut self, index: Id) -> Option<&Node> {
-        let parent = self.nodes.get(index).unwrap();
-        let player = parent.player;
-        let mv = parent.untried_moves.first().unwrap().clone();
-        self.nodes[index].untried_moves.remove(0);
-        let mut board = self.nodes[index].state.clone();
+    /// Position of node `index`, replayed from the root
+    pub fn state(&self, index: Id) -> Board {
+        let mut moves = vec![];
+        let mut cur = index;
+        while !self.nodes[cur].is_root() {
+            let parent = self.nodes[cur].parent;
+            moves.push((self.nodes[cur].action.unwrap(), self.nodes[parent].player));
+            cur = parent;
+        }
+        let mut board = self.state.clone();
+        for (mv, player) in moves.iter().rev() {
+            board.place(mv.x, mv.y, *player);
+        }
+        board
+    }
+
+    // The node whose state is `board`, reached from the root by the stones added since
+    fn find_position(&self, board: &Board) -> Option<Id> {
+        let root = &self.state;
+        if !root.same_rules(board) {
+            return None;
+        }
+        let mut played = vec![];
+        for i in 0..board.height as i32 {
+            for j in 0..board.width as i32 {
+                match (root.get(i, j), board.get(i, j)) {
+                    (Some(0), Some(p)) if p != 0 => played.push((i as usize, j as usize, p)),
+                    (a, b) if a != b => return None,
+                    _ => (),
+                }
+            }
+        }
+        let mut cur = 0;
+        while !played.is_empty() {
+            let player = self.nodes[cur].player;
+            let (next, k) = self.nodes[cur].children.iter().find_map(|c| {
+                let mv = self.nodes[*c].action.unwrap();
+                played
+                    .iter()
+                    .position(|&(x, y, p)| (x, y, p) == (mv.x, mv.y, player))
+                    .map(|k| (*c, k))
+            })?;
+            played.swap_remove(k);
+            cur = next;
+        }
+        Some(cur)
+    }
+
+    fn advance(&mut self, board: &Board) -> bool {
+        match self.find_position(board) {
+            Some(0) => true,
+            Some(index) => {
+                self.reroot(index);
+                true
+            }
+            None => {
+                let tree = self.new_like(board.clone(), board.next_player());
+                *self = tree;
+                false
+            }
+        }
+    }
+
+    // Make `index` the root, everything outside of its subtree is dropped from the arena
+    fn reroot(&mut self, index: Id) {
+        self.state = self.state(index);
+        let mut old: Vec<Option<Node>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        // Breadth first, so a parent always gets its new id before its children
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back((index, 0));
+        while let Some((id, parent)) = queue.pop_front() {
+            let mut node = old[id].take().unwrap();
+            let new_id = self.nodes.len();
+            node.index = new_id;
+            node.parent = if new_id == 0 { 0 } else { parent };
+            for c in std::mem::take(&mut node.children) {
+                queue.push_back((c, new_id));
+            }
+            self.nodes.push(node);
+            if new_id != 0 {
+                self.nodes[parent].children.push(new_id);
+            }
+        }
+    }
+
+    // Generate the moves of node `index` the first time it is expanded. With PUCT
+    // the moves get their priors and are sorted by them, best first.
+    fn generate_moves(&mut self, index: Id, board: &mut Board) {
+        if self.nodes[index].untried_moves.is_some() {
+            return;
+        }
+        let player = self.nodes[index].player;
+        let mut moves = board.gen_ordered_moves_all(player);
+        self.evaluations += 1;
+        if let Selection::Puct { .. } = self.config.selection {
+            let priors = self.prior.priors(board, player, &moves);
+            assert_eq!(priors.len(), moves.len());
+            let mut pairs: Vec<(Move, f32)> = moves.into_iter().zip(priors).collect();
+            pairs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            let (sorted, priors) = pairs.into_iter().unzip();
+            moves = sorted;
+            self.nodes[index].untried_priors = priors;
+        }
+        self.nodes[index].untried_moves = Some(moves);
+    }
 
+    // Whether node `index`, at position `board`, has moves left to expand
+    fn has_untried_moves(&mut self, index: Id, board: &mut Board) -> bool {
+        self.generate_moves(index, board);
+        !self.nodes[index].untried_moves.as_ref().unwrap().is_empty()
+    }
+
+    // Add the next untried move of node `index` as a child, `board` moves along with it
+    fn expand(&mut self, index: Id, board: &mut Board) -> Id {
+        self.generate_moves(index, board);
+        let node = &mut self.nodes[index];
+        let player = node.player;
+        let moves = node.untried_moves.as_mut().unwrap();
+        let mv = moves.remove(0);
+        let prior = if node.untried_priors.is_empty() {
+            0.0
+        } else {
+            node.untried_priors.remove(0)
+        };
+        if moves.is_empty() {
+            // Give the memory back as soon as the node is fully expanded
+            *moves = Vec::new();
+            node.untried_priors = Vec::new();
+        }
         board.place(mv.x, mv.y, player);
-        let next_player = cfg::opponent(player);
-        self.new_node(index, board.clone(), next_player, Some(mv))
+        let terminal = board.is_win_at(mv.x, mv.y);
+        let child = self.new_node(index, cfg::opponent(player), Some(mv), terminal);
+        self.nodes[child].prior = prior;
+        child
     }
 
     fn backpropagete(&mut self, index: Id, winner: Option<u8>) {
@@ -77,48 +331,37 @@ impl Tree {
         }
     }
 
-    fn rollout_policy(&self, moves: &Vec<Move>) -> Option<Move> {
-        if moves.len() == 0 {
-            return None;
-        }
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "random")] {
-                let mut rng = rand::thread_rng();
-                let mut idx = 0;
-                loop {
-                    if idx + 1 < moves.len()
-                    && moves[idx].score == moves[idx + 1].score
-                    && moves[idx].original_score == moves[idx + 1].original_score
-                    {
-                        idx += 1;
-                    } else {
-                        break;
-                    }
-                }
-                let idx = rng.gen_range(0..idx + 1);
-                Some(moves[idx])
-            } else {
-                Some(moves[0])
-            }
-        }
+    /// Play out the game from node `index`, whose position is `current_state`.
+    /// A game which is not over after the rollout depth is decided by `static_winner`.
+    pub fn rollout(&mut self, index: Id, current_state: Board) -> Option<u8> {
+        self.playout(index, current_state, &mut vec![])
     }
 
-    pub fn rollout(&mut self, index: Id) -> Option<u8> {
-        let mut current_state = self.nodes[index].state.clone();
+    // Same as `rollout`, the moves played are added to `played` with their player
+    fn playout(
+        &mut self,
+        index: Id,
+        mut current_state: Board,
+        played: &mut Vec<(usize, usize, u8)>,
+    ) -> Option<u8> {
         let mut player = self.nodes[index].player;
-        let mut count = 0;
-        loop {
-            count += 1;
-            if count >= 10 {
-                return None;
-            }
+        match self.nodes[index].proof {
+            Proof::Win => return Some(cfg::opponent(player)),
+            Proof::Loss => return Some(player),
+            Proof::Unknown => (),
+        }
+        let depth = self
+            .rollout
+            .max_depth()
+            .unwrap_or(self.config.rollout_depth);
+        for _ in 0..depth {
             let moves = current_state.gen_ordered_moves_all(player);
-            let rollout_move = self.rollout_policy(&moves);
-            if rollout_move.is_none() {
-                return None;
-            }
-            let mv = rollout_move.unwrap();
+            self.evaluations += 1;
+            let mv = self
+                .rollout
+                .choose(&current_state, player, &moves, &mut self.rng)?;
             current_state.place(mv.x, mv.y, player);
+            played.push((mv.x, mv.y, player));
             player = cfg::opponent(player);
             if mv.is_dead_move() {
                 let winner = current_state.any_winner();
@@ -127,23 +370,178 @@ impl Tree {
                 }
             }
         }
+        self.evaluations += 1;
+        static_winner(&mut current_state, player)
+    }
+
+    // Select and expand a node, returns it with its position and depth
+    fn select(&mut self) -> (Id, Board, i32) {
+        let mut board = self.state.clone();
+        let mut cur = 0;
+        let mut depth = 1;
+        // Proven nodes are not searched any further
+        while self.nodes[cur].proof == Proof::Unknown {
+            let untried = self.has_untried_moves(cur, &mut board);
+            if !untried && self.nodes[cur].children.is_empty() {
+                // The board is full
+                break;
+            }
+            match self.choose_child(cur, untried) {
+                None => {
+                    let n = self.expand(cur, &mut board);
+                    return (n, board, depth);
+                }
+                Some(child) => {
+                    let player = self.nodes[cur].player;
+                    cur = child;
+                    let mv = self.nodes[cur].action.unwrap();
+                    board.place(mv.x, mv.y, player);
+                    depth += 1;
+                }
+            }
+        }
+        (cur, board, 0)
+    }
+
+    // The child to descend into, or `None` to expand the next untried move first.
+    // UCT and RAVE try every move once before descending, PUCT only expands the
+    // next move when its prior makes it look better than the best child.
+    fn choose_child(&self, index: Id, untried: bool) -> Option<Id> {
+        let node = &self.nodes[index];
+        if untried && node.children.is_empty() {
+            return None;
+        }
+        match self.config.selection {
+            Selection::Puct { c_puct } if untried => {
+                let best = self.best_child(index);
+                let c = &self.nodes[best];
+                // An unvisited move has no value yet, only its exploration term
+                let prior = node.untried_priors.first().copied().unwrap_or(0.0) as f64;
+                let unvisited = c_puct * prior * node.n().sqrt();
+                if c.proof == Proof::Loss || unvisited > self.selection_value(c, node.n()) {
+                    None
+                } else {
+                    Some(best)
+                }
+            }
+            _ if untried => None,
+            _ => Some(self.best_child(index)),
+        }
+    }
+
+    // One simulation: selection, expansion, rollout and backpropagation
+    fn simulate(&mut self) -> i32 {
+        let (v, board, depth) = self.select();
+        let mut played = vec![];
+        let r = self.playout(v, board, &mut played);
+        self.backpropagete(v, r);
+        if let Selection::Rave { .. } = self.config.selection {
+            self.update_amaf(v, &played, r);
+        }
+        if self.nodes[v].proof != Proof::Unknown && !self.nodes[v].is_root() {
+            self.update_proof(self.nodes[v].parent);
+        }
+        depth
+    }
+
+    /* AMAF update: walking up from the leaf, every child of a node on the path
+     * whose move was played later in the simulation by the player to move at
+     * that node gets the result, as if its move had been played first.
+     */
+    fn update_amaf(&mut self, leaf: Id, rollout: &[(usize, usize, u8)], winner: Option<u8>) {
+        let width = self.state.width;
+        // Player of the stone played at each cell after the current node, 0 for none
+        let mut later = vec![0u8; width * self.state.height];
+        for &(x, y, player) in rollout {
+            later[x * width + y] = player;
+        }
+        let mut cur = leaf;
+        loop {
+            let player = self.nodes[cur].player;
+            for i in 0..self.nodes[cur].children.len() {
+                let c = self.nodes[cur].children[i];
+                let mv = self.nodes[c].action.unwrap();
+                if later[mv.x * width + mv.y] != player {
+                    continue;
+                }
+                let child = &mut self.nodes[c];
+                child.amaf_count += 1;
+                if winner == Some(player) {
+                    child.amaf_win_count += 1;
+                } else if winner == Some(cfg::opponent(player)) {
+                    child.amaf_loss_count += 1;
+                }
+            }
+            if self.nodes[cur].is_root() {
+                return;
+            }
+            let parent = self.nodes[cur].parent;
+            let mv = self.nodes[cur].action.unwrap();
+            later[mv.x * width + mv.y] = self.nodes[parent].player;
+            cur = parent;
+        }
+    }
+
+    /* MCTS-Solver: a node is lost for the player who moved into it as soon as
+     * the opponent has a winning reply, and won once every reply loses.
+     * Proofs are pushed up towards the root until nothing changes.
+     */
+    fn update_proof(&mut self, mut index: Id) {
+        loop {
+            let node = &self.nodes[index];
+            if node.proof == Proof::Unknown {
+                let mut children = node.children.iter().map(|c| self.nodes[*c].proof);
+                let proof = if children.clone().any(|p| p == Proof::Win) {
+                    Proof::Loss
+                } else if node.is_fully_expanded()
+                    && !node.children.is_empty()
+                    && children.all(|p| p == Proof::Loss)
+                {
+                    Proof::Win
+                } else {
+                    return;
+                };
+                self.nodes[index].proof = proof;
+            }
+            if self.nodes[index].is_root() {
+                return;
+            }
+            index = self.nodes[index].parent;
+        }
+    }
+
+    fn root_children(&self) -> impl Iterator<Item = &Node> {
+        self.nodes[0].children.iter().map(move |c| &self.nodes[*c])
+    }
+
+    /// Whether the result of the game from the root is known
+    pub fn is_proven(&self) -> bool {
+        self.nodes[0].proof != Proof::Unknown
     }
 
     pub fn best_child(&self, index: usize) -> usize {
-        let c_param = 0.7;
         let node = &self.nodes[index];
 
         if node.children.is_empty() {
             return index;
         }
 
+        // A proven win is always taken, proven losses only when nothing else is left
+        let children = node.children.iter().map(|c| &self.nodes[*c]);
+        if let Some(c) = children.clone().find(|c| c.proof == Proof::Win) {
+            return c.index;
+        }
+        let all_lost = children.clone().all(|c| c.proof == Proof::Loss);
+
         let mut res = node.children[0];
         let mut cur_max = f64::MIN;
 
         for i in 0..node.children.len() {
             let c = &self.nodes[node.children[i]];
-            let r = (2.0 * node.n().ln() / c.n()).sqrt();
-            let v = c.q() / c.n() + c_param * r;
+            if c.proof == Proof::Loss && !all_lost {
+                continue;
+            }
+            let v = self.selection_value(c, node.n());
             if v > cur_max {
                 cur_max = v;
                 res = c.index;
@@ -152,12 +550,95 @@ impl Tree {
         return res;
     }
 
+    fn selection_value(&self, c: &Node, parent_n: f64) -> f64 {
+        let exploration = self.config.exploration;
+        match self.config.selection {
+            Selection::Uct => uct(c.q(), c.n(), parent_n, exploration),
+            Selection::Rave { equivalence } => {
+                // beta goes from 1 to 0 as the child collects its own visits
+                let beta = (equivalence / (3.0 * c.n() + equivalence)).sqrt();
+                let amaf = if c.amaf_count > 0 {
+                    (c.amaf_win_count as i32 - c.amaf_loss_count as i32) as f64
+                        / c.amaf_count as f64
+                } else {
+                    0.0
+                };
+                (1.0 - beta) * c.q() / c.n()
+                    + beta * amaf
+                    + exploration * (2.0 * parent_n.ln() / c.n()).sqrt()
+            }
+            Selection::Puct { c_puct } => {
+                c.q() / c.n() + c_puct * c.prior as f64 * parent_n.sqrt() / (1.0 + c.n())
+            }
+        }
+    }
+
+    // Node `index` with its subtree, `pv` marks it as part of the principal variation
+    fn export_node(
+        &self,
+        index: Id,
+        options: &ExportOptions,
+        depth: usize,
+        pv: bool,
+    ) -> ExportNode {
+        let node = &self.nodes[index];
+        let mut children = vec![];
+        if depth < options.max_depth && !node.children.is_empty() {
+            // The move the search plays at the root, the most visited reply below it
+            let pv_child = if node.is_root() {
+                self.best_child(index)
+            } else {
+                *node
+                    .children
+                    .iter()
+                    .max_by_key(|c| self.nodes[**c].visited_count)
+                    .unwrap()
+            };
+            for c in node.children.iter() {
+                if (self.nodes[*c].visited_count as u64) < options.min_visits {
+                    continue;
+                }
+                children.push(self.export_node(*c, options, depth + 1, pv && *c == pv_child));
+            }
+        }
+        ExportNode {
+            mv: node.action.map(|mv| (mv.x, mv.y)),
+            to_move: node.player,
+            visits: node.visited_count as u64,
+            wins: node.win_count as u64,
+            losses: node.loss_count as u64,
+            score: node.action.map_or(0, |mv| mv.score),
+            value: if node.visited_count > 0 {
+                node.q() / node.n()
+            } else {
+                0.0
+            },
+            pv,
+            children,
+        }
+    }
+
+    fn progress(&self, depth: i32) -> SearchProgress {
+        let action = self.nodes[self.best_child(0)].action;
+        SearchProgress {
+            depth,
+            nodes: self.nodes.len() as u64,
+            best_move: action.map(|mv| (mv.x, mv.y)),
+            score: action.map_or(0, |mv| mv.score),
+        }
+    }
+
     pub fn best_move(&self, index: usize) -> usize {
         let mut res = -1;
         let mut cur_max = f64::MIN;
         let node = &self.nodes[index];
         for i in 0..node.children.len() {
             let c = &self.nodes[node.children[i]];
+            match c.proof {
+                Proof::Win => return c.index,
+                Proof::Loss => continue,
+                Proof::Unknown => (),
+            }
             let v = c.win_count as f64 / c.visited_count as f64;
             if v > cur_max && c.visited_count >= 8 {
                 cur_max = v;
@@ -173,22 +654,26 @@ impl Tree {
 }
 
 impl Node {
-    pub fn new(index: Id, parent: Id, state: Board, player: u8, mv: Option<Move>) -> Self {
-        let mut s = Node {
+    pub fn new(index: Id, parent: Id, player: u8, mv: Option<Move>, terminal: bool) -> Self {
+        Node {
             visited_count: 0,
             win_count: 0,
             loss_count: 0,
-            state: state.clone(),
-            untried_moves: vec![],
+            amaf_count: 0,
+            amaf_win_count: 0,
+            amaf_loss_count: 0,
+            untried_moves: None,
+            untried_priors: vec![],
+            prior: 0.0,
             children: vec![],
             player,
+            terminal,
+            // The move which ends the game wins it
+            proof: if terminal { Proof::Win } else { Proof::Unknown },
             parent,
             index,
             action: mv,
-        };
-
-        s.untried_moves = s.state.gen_ordered_moves_all(player);
-        s
+        }
     }
 
     pub fn is_root(&self) -> bool {
@@ -204,71 +689,396 @@ impl Node {
     }
 
     pub fn is_fully_expanded(&self) -> bool {
-        self.untried_moves.len() == 0
+        matches!(&self.untried_moves, Some(moves) if moves.is_empty())
     }
 
     pub fn is_terminal_node(&self) -> bool {
-        self.state.any_winner() != None
+        self.terminal
     }
 }
 
+fn uct(q: f64, n: f64, parent_n: f64, c_param: f64) -> f64 {
+    q / n + c_param * (2.0 * parent_n.ln() / n).sqrt()
+}
+
+// Simulations run when a search is given no limit at all
+const DEFAULT_SIMULATIONS: u64 = 3000;
+
 pub struct MonteCarlo {
     tree: Tree,
-    simulate_count: u32,
+    // Extra trees searched by the other threads, their root statistics are merged
+    helpers: Vec<Tree>,
+    threads: usize,
+    // Workers of the trees, built by `set_threads` when there are several
+    pool: Option<rayon::ThreadPool>,
+    limits: SearchLimits,
+    max_depth: i32,
+    control: SearchControl,
     debug: bool,
 }
 
 impl MonteCarlo {
     pub fn new(state: Board, player: u8, simulate_count: u32) -> Self {
-        let mut s = Self {
-            tree: Tree::new(),
-            simulate_count,
+        Self::new_with_limits(state, player, SearchLimits::nodes(simulate_count as u64))
+    }
+
+    /// Search until the time or simulation limit runs out, whichever comes first
+    pub fn new_with_limits(state: Board, player: u8, limits: SearchLimits) -> Self {
+        Self {
+            tree: Tree::new(state, player),
+            helpers: vec![],
+            threads: 1,
+            pool: None,
+            limits,
+            max_depth: 0,
+            control: SearchControl::default(),
             debug: match env::var("GOMOKU_DEBUG") {
                 Ok(_) => true,
                 _ => false,
             },
-        };
-        s.tree.new_node(0, state, player, None);
-        s
+        }
+    }
+
+    /// Move the root to `board` for the next search. If `board` follows from the
+    /// current root by moves already explored, the statistics below it are kept and
+    /// the rest of the tree is dropped, otherwise a fresh tree is started.
+    /// Returns whether the old tree could be reused.
+    pub fn advance(&mut self, board: &Board) -> bool {
+        self.max_depth = 0;
+        for helper in self.helpers.iter_mut() {
+            helper.advance(board);
+        }
+        self.tree.advance(board)
+    }
+
+    /// Root parallel search: every thread grows its own tree, the statistics
+    /// of the root moves are added up to pick the move. When the threads can
+    /// not be started it searches on its own.
+    pub fn set_threads(&mut self, threads: usize) {
+        if threads != self.threads {
+            self.pool = if threads > 1 {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .ok()
+            } else {
+                None
+            };
+        }
+        self.threads = self.pool.as_ref().map_or(1, |p| p.current_num_threads());
+        self.helpers.truncate(self.threads - 1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Select children with UCT or RAVE, see `MonteCarloConfig`
+    pub fn set_config(&mut self, config: MonteCarloConfig) {
+        self.tree.set_config(config);
+        for helper in self.helpers.iter_mut() {
+            helper.set_config(config);
+        }
+    }
+
+    pub fn config(&self) -> MonteCarloConfig {
+        self.tree.config
+    }
+
+    /// Source of the move priors used by PUCT, `HeuristicPrior` by default
+    pub fn set_prior_policy(&mut self, prior: Arc<dyn PriorPolicy>) {
+        for helper in self.helpers.iter_mut() {
+            helper.set_prior_policy(prior.clone());
+        }
+        self.tree.set_prior_policy(prior);
+    }
+
+    /// Draw the random numbers of the search from `seed`. A single threaded search
+    /// with a simulation limit then always returns the same move for a position.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.tree.set_seed(seed);
+        for helper in self.helpers.iter_mut() {
+            helper.rng = self.tree.rng.fork();
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.tree.rng.seed()
+    }
+
+    /// Policy playing the rollouts, `HeuristicRollout` by default
+    pub fn set_rollout_policy(&mut self, rollout: Arc<dyn RolloutPolicy>) {
+        for helper in self.helpers.iter_mut() {
+            helper.set_rollout_policy(rollout.clone());
+        }
+        self.tree.set_rollout_policy(rollout);
+    }
+
+    /// Attach a stop token and progress callback, checked between simulations
+    pub fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    /// Budget of the next searches, `DEFAULT_SIMULATIONS` when it has no limits
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    /// Budget the next search runs with
+    pub fn limits(&self) -> SearchLimits {
+        if self.limits.is_unlimited() {
+            SearchLimits {
+                nodes: Some(DEFAULT_SIMULATIONS),
+                ..self.limits
+            }
+        } else {
+            self.limits
+        }
     }
 
     fn get(&self, index: usize) -> &Node {
         self.tree.get_node(index).unwrap()
     }
 
-    fn tree_policy(&mut self) -> usize {
-        let mut cur = 0;
-        while !self.get(cur).is_terminal_node() {
-            if !self.get(cur).is_fully_expanded() {
-                let n = self.tree.expand(cur);
-                return n.unwrap().index;
-            } else {
-                cur = self.tree.best_child(cur);
+    fn report_progress(&self) {
+        self.control.report(self.tree.progress(self.max_depth));
+    }
+
+    /// Keep adding simulations to the tree until the search is stopped,
+    /// or `max_simulations` more have been run
+    pub fn ponder(&mut self, max_simulations: u32) {
+        for _ in 0..max_simulations {
+            if self.control.is_stopped() {
+                break;
             }
+            self.tree.simulate();
         }
-        return cur;
+    }
+
+    /// The most visited answer to the move `search_move` picks, used to guess the opponent's reply
+    pub fn predicted_reply(&self) -> Option<Move> {
+        let best = self.tree.best_child(0);
+        self.get(best)
+            .children
+            .iter()
+            .map(|c| self.get(*c))
+            .max_by_key(|c| c.visited_count)
+            .and_then(|c| c.action)
     }
 
     pub fn search_move(&mut self) -> Move {
-        for i in 0..self.simulate_count {
-            let v = self.tree_policy();
+        self.search().best_move
+    }
+
+    /// Run simulations until a limit is reached or the search is stopped
+    pub fn search(&mut self) -> SearchResult {
+        let timer = Timer::start();
+        let max_simulations = self.limits().nodes.unwrap_or(u64::MAX);
+        let evaluations = self.evaluations();
+        if self.threads > 1 {
+            return self.search_parallel(&timer, max_simulations, evaluations);
+        }
+        let mut simulations = 0;
+        while simulations < max_simulations {
+            // Run at least one simulation, so the root always has a child to return
+            if simulations > 0
+                && (self.control.is_stopped() || self.out_of_time(&timer) || self.tree.is_proven())
+            {
+                break;
+            }
+            if simulations > 0 && simulations % 100 == 0 {
+                self.report_progress();
+            }
+            let depth = self.tree.simulate();
+            self.max_depth = std::cmp::max(self.max_depth, depth);
             if self.debug {
-                if i % 100 == 0 {
+                if simulations % 100 == 0 {
                     println!(
-                        "%{:.2} {} : rollout {}",
-                        (i as f32 * 100.0) / self.simulate_count as f32,
-                        v,
-                        self.tree.nodes.len()
+                        "{} : rollout {} ({} ms)",
+                        simulations,
+                        self.tree.nodes.len(),
+                        timer.elapsed_ms()
                     );
                 }
             }
-            let r = self.tree.rollout(v);
-            self.tree.backpropagete(v, r);
+            simulations += 1;
         }
+        self.report_progress();
+        let stats = SearchStats {
+            depth: self.max_depth,
+            nodes: self.tree.nodes.len() as u64,
+            evaluations: self.evaluations() - evaluations,
+            simulations,
+            tt_hits: 0,
+            elapsed_ms: timer.elapsed_ms(),
+            threads: self.threads,
+        };
+        // A full or decided board leaves the root without children
         let best = self.tree.best_child(0);
-        let res = self.get(best).action.unwrap();
+        let res = match self.get(best).action {
+            Some(res) => res,
+            None => return SearchResult::no_move(stats, self.limits()),
+        };
         self.print_debug(best, &res);
-        res
+        SearchResult {
+            best_move: res,
+            stats,
+            limits: self.limits(),
+        }
+    }
+
+    // `evaluations` were done by the trees before the search
+    fn search_parallel(
+        &mut self,
+        timer: &Timer,
+        max_simulations: u64,
+        evaluations: u64,
+    ) -> SearchResult {
+        while self.helpers.len() + 1 < self.threads {
+            let root = self.get(0).player;
+            let helper = self.tree.new_like(self.tree.state.clone(), root);
+            self.helpers.push(helper);
+        }
+        let started = AtomicU64::new(0);
+        let finished = AtomicU64::new(0);
+        let control = &self.control;
+        let limits = self.limits;
+        let pool = self.pool.as_ref().unwrap();
+        let mut trees: Vec<&mut Tree> = std::iter::once(&mut self.tree)
+            .chain(self.helpers.iter_mut())
+            .collect();
+        let depth = pool.install(|| {
+            trees
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, tree)| {
+                    let mut max_depth = 0;
+                    loop {
+                        let n = started.fetch_add(1, Ordering::Relaxed);
+                        // The first simulation always runs, so the root has a child to return
+                        if n >= max_simulations
+                            || (n > 0
+                                && (control.is_stopped()
+                                    || out_of_time(&limits, timer)
+                                    || tree.is_proven()))
+                        {
+                            break;
+                        }
+                        max_depth = std::cmp::max(max_depth, tree.simulate());
+                        let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                        if i == 0 && done.is_multiple_of(100) {
+                            control.report(tree.progress(max_depth));
+                        }
+                    }
+                    max_depth
+                })
+                .max()
+                .unwrap_or(0)
+        });
+        self.max_depth = std::cmp::max(self.max_depth, depth);
+        let stats = SearchStats {
+            depth: self.max_depth,
+            nodes: self.node_count(),
+            evaluations: self.evaluations() - evaluations,
+            simulations: finished.load(Ordering::Relaxed),
+            tt_hits: 0,
+            elapsed_ms: timer.elapsed_ms(),
+            threads: self.threads,
+        };
+        let best_move = match self.merged_best_move() {
+            Some(best_move) => best_move,
+            None => return SearchResult::no_move(stats, self.limits()),
+        };
+        self.control.report(SearchProgress {
+            depth: self.max_depth,
+            nodes: self.node_count(),
+            best_move: Some((best_move.x, best_move.y)),
+            score: best_move.score,
+        });
+        SearchResult {
+            best_move,
+            stats,
+            limits: self.limits(),
+        }
+    }
+
+    // Pick a root move with the statistics of all trees added up, None when the
+    // root has no children
+    fn merged_best_move(&self) -> Option<Move> {
+        let trees = || std::iter::once(&self.tree).chain(self.helpers.iter());
+        // A move proven in any tree decides
+        if let Some(c) = trees()
+            .flat_map(|t| t.root_children())
+            .find(|c| c.proof == Proof::Win)
+        {
+            return c.action;
+        }
+        let lost: Vec<(usize, usize)> = trees()
+            .flat_map(|t| t.root_children())
+            .filter(|c| c.proof == Proof::Loss)
+            .map(|c| (c.action.unwrap().x, c.action.unwrap().y))
+            .collect();
+        let mut merged: Vec<(Move, f64, f64)> = vec![];
+        for tree in trees() {
+            for c in tree.root_children() {
+                let mv = c.action.unwrap();
+                if lost.contains(&(mv.x, mv.y)) {
+                    continue;
+                }
+                match merged
+                    .iter_mut()
+                    .find(|(m, _, _)| (m.x, m.y) == (mv.x, mv.y))
+                {
+                    Some(entry) => {
+                        entry.1 += c.q();
+                        entry.2 += c.n();
+                    }
+                    None => merged.push((mv, c.q(), c.n())),
+                }
+            }
+        }
+        if merged.is_empty() {
+            // Every move loses
+            return self.tree.nodes[self.tree.best_child(0)].action;
+        }
+        let total: f64 = merged.iter().map(|(_, _, n)| n).sum();
+        let c_param = self.tree.config.exploration;
+        merged
+            .iter()
+            .max_by(|a, b| {
+                let va = uct(a.1, a.2, total, c_param);
+                let vb = uct(b.1, b.2, total, c_param);
+                va.partial_cmp(&vb).unwrap()
+            })
+            .map(|(mv, _, _)| *mv)
+    }
+
+    /// The tree below the root, to look at a search with Graphviz or attach it to a
+    /// bug report. With several threads only the tree of the first one is exported.
+    pub fn export_tree(&self, options: &ExportOptions) -> SearchTreeExport {
+        let state = &self.tree.state;
+        SearchTreeExport::new(
+            "monte_carlo",
+            state.to_string(),
+            state.width,
+            state.height,
+            self.tree.export_node(0, options, 0, true),
+            options,
+        )
+    }
+
+    fn node_count(&self) -> u64 {
+        let helpers: usize = self.helpers.iter().map(|t| t.nodes.len()).sum();
+        (self.tree.nodes.len() + helpers) as u64
+    }
+
+    fn evaluations(&self) -> u64 {
+        let helpers: u64 = self.helpers.iter().map(|t| t.evaluations).sum();
+        self.tree.evaluations + helpers
+    }
+
+    fn out_of_time(&self, timer: &Timer) -> bool {
+        out_of_time(&self.limits, timer)
     }
 
     fn print_debug(&self, best: usize, mv: &Move) {
@@ -285,7 +1095,7 @@ impl MonteCarlo {
                 ]);
             }
             println!("best move: {:?}", mv);
-            self.tree.nodes[best].state.print_debug(&moves, &score, &mv);
+            self.tree.state(best).print_debug(&moves, &score, mv);
             println!("win: {}", self.tree.nodes[best].win_count);
             println!("loss: {}", self.tree.nodes[best].loss_count);
             println!("visited: {}", self.tree.nodes[best].visited_count);
@@ -293,52 +1103,153 @@ impl MonteCarlo {
     }
 }
 
-impl GomokuSolver for MonteCarlo {
-    fn best_move(input: &str, width: usize, height: usize) -> Move {
-        let board = Board::new(input.to_string(), width, height);
+fn out_of_time(limits: &SearchLimits, timer: &Timer) -> bool {
+    match limits.time_ms {
+        Some(time_ms) => timer.elapsed_ms() >= time_ms,
+        None => false,
+    }
+}
+
+/// The "monte_carlo" engines, the tree is re-rooted at every new position
+/// and kept as long as the game goes on
+pub struct MonteCarloEngine {
+    name: String,
+    config: MonteCarloConfig,
+    options: EngineOptions,
+    monte: MonteCarlo,
+}
+
+impl MonteCarloEngine {
+    pub fn new(name: &str, config: MonteCarloConfig, options: &EngineOptions) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            options: options.clone(),
+            monte: Self::fresh_search(config, options, SearchControl::default()),
+        }
+    }
+
+    // A search on the empty board, without anything kept from before
+    fn fresh_search(
+        config: MonteCarloConfig,
+        options: &EngineOptions,
+        control: SearchControl,
+    ) -> MonteCarlo {
+        let board = Board::new_default();
         let player = board.next_player();
-        let mut monte = MonteCarlo::new(board, player, 3000);
-        monte.search_move()
+        let mut monte = MonteCarlo::new_with_limits(board, player, SearchLimits::default());
+        monte.set_config(config);
+        monte.set_threads(options.threads());
+        monte.set_seed(options.seed.unwrap_or_else(random_seed));
+        monte.set_control(control);
+        monte
+    }
+}
+
+impl Engine for MonteCarloEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) {
+        let control = self.monte.control.clone();
+        self.monte = Self::fresh_search(self.config, &self.options, control);
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.monte.advance(board);
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.monte.set_control(control);
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.monte.control.stop.clone()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        self.monte.set_limits(*limits);
+        self.monte.search()
+    }
+
+    fn predicted_reply(&self) -> Option<Move> {
+        self.monte.predicted_reply()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::policy::TruncatedRollout;
 
     #[test]
     fn test_node_create() {
         let mut board = Board::new_default();
         board.place(5, 5, 1);
-        let mut root = Tree::new();
-        root.new_node(0, board, 1, None).unwrap();
+        let mut root = Tree::new(board.clone(), 1);
         assert_eq!(root.get_node(0).unwrap().parent, 0);
 
-        assert_eq!(root.get_node(0).unwrap().untried_moves.len(), 8);
+        // Moves are generated on the first expansion
+        assert!(root.get_node(0).unwrap().untried_moves.is_none());
+        assert!(root.has_untried_moves(0, &mut board));
+        assert_eq!(
+            root.get_node(0)
+                .unwrap()
+                .untried_moves
+                .as_ref()
+                .unwrap()
+                .len(),
+            8
+        );
 
-        root.expand(0);
+        root.expand(0, &mut board);
         assert_eq!(root.get_node(0).unwrap().parent, 0);
-        assert_eq!(root.get_node(0).unwrap().untried_moves.len(), 7);
+        assert_eq!(
+            root.get_node(0)
+                .unwrap()
+                .untried_moves
+                .as_ref()
+                .unwrap()
+                .len(),
+            7
+        );
         assert_eq!(root.get_node(1).unwrap().parent, 0);
+        assert_eq!(root.state(1).to_string(), board.to_string());
         root.backpropagete(1, Some(2));
 
-        assert_eq!(root.get_node(1).unwrap().untried_moves.len(), 8);
-        root.expand(1);
-        assert_eq!(root.get_node(1).unwrap().untried_moves.len(), 7);
+        assert!(root.get_node(1).unwrap().untried_moves.is_none());
+        root.expand(1, &mut board);
+        assert_eq!(
+            root.get_node(1)
+                .unwrap()
+                .untried_moves
+                .as_ref()
+                .unwrap()
+                .len(),
+            7
+        );
+        assert_eq!(root.state(2).to_string(), board.to_string());
     }
 
     #[test]
     fn test_tree() {
-        let mut root = Tree::new();
-        assert_eq!(root.nodes.len(), 0);
-        let node = root.new_node(0, Board::new_default(), 1, None).unwrap();
+        let mut board = Board::new_default();
+        let mut root = Tree::new(board.clone(), 1);
+        assert_eq!(root.nodes.len(), 1);
+        let node = root.get_node(0).unwrap();
         assert_eq!(node.parent, 0);
         assert_eq!(node.index, 0);
         assert_eq!(node.is_root(), true);
 
-        root.nodes[0].untried_moves.push(Move::new(1, 2, 0, 0));
-        let node_2 = root.expand(0).unwrap();
-        assert_eq!(node_2.parent, 0);
+        assert!(root.has_untried_moves(0, &mut board));
+        root.nodes[0]
+            .untried_moves
+            .as_mut()
+            .unwrap()
+            .push(Move::new(1, 2, 0, 0));
+        let node_2 = root.expand(0, &mut board);
+        assert_eq!(root.get_node(node_2).unwrap().parent, 0);
         assert_eq!(root.get_node(0).unwrap().children.len(), 1);
         assert_eq!(root.get_node(1).unwrap().children.len(), 0);
         assert_eq!(root.get_node(1).unwrap().player, 2);
@@ -357,13 +1268,22 @@ mod tests {
     fn test_rollout() {
         let mut board = Board::new_default();
         board.place(5, 5, 1);
-        let mut root = Tree::new();
-        root.new_node(0, board, 2, None).unwrap();
+        let mut root = Tree::new(board.clone(), 2);
         assert_eq!(root.get_node(0).unwrap().parent, 0);
         assert_eq!(root.get_node(0).unwrap().player, 2);
 
-        let res = root.rollout(0);
+        let res = root.rollout(0, board.clone());
         assert_eq!(res.is_some(), false);
+
+        // A rollout cut off at once is decided by the static evaluation
+        let mut board = Board::new_default();
+        for j in 6..9 {
+            board.place(7, j, 1);
+        }
+        board.place(0, 0, 2);
+        let mut root = Tree::new(board.clone(), 2);
+        root.set_rollout_policy(Arc::new(TruncatedRollout { depth: 0 }));
+        assert_eq!(root.rollout(0, board), Some(1));
     }
 
     #[test]
@@ -376,4 +1296,310 @@ mod tests {
         println!("{:?}", mv);
         assert!((mv.x as i32 - 7 as i32).abs() <= 1 && (mv.y as i32 - 7 as i32).abs() <= 1);
     }
+
+    #[test]
+    fn test_monte_carlo_stop() {
+        use crate::search::StopToken;
+
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let stop = StopToken::new();
+        stop.stop();
+        let mut monte_carlo = MonteCarlo::new(board, 2, 3000);
+        monte_carlo.set_control(SearchControl::new(stop, None));
+        let mv = monte_carlo.search_move();
+        assert_eq!(monte_carlo.get(0).visited_count, 1);
+        assert!((mv.x as i32 - 7).abs() <= 1 && (mv.y as i32 - 7).abs() <= 1);
+    }
+
+    #[test]
+    fn test_monte_carlo_advance() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 500);
+        let mv = monte_carlo.search_move();
+        let best = monte_carlo.tree.best_child(0);
+        let reply = monte_carlo.predicted_reply().unwrap();
+        let visited = monte_carlo.tree.nodes[best]
+            .children
+            .iter()
+            .map(|c| &monte_carlo.tree.nodes[*c])
+            .find(|c| c.action.map(|a| (a.x, a.y)) == Some((reply.x, reply.y)))
+            .unwrap()
+            .visited_count;
+        let size = monte_carlo.tree.nodes.len();
+
+        board.place(mv.x, mv.y, 2);
+        board.place(reply.x, reply.y, 1);
+        assert!(monte_carlo.advance(&board));
+        let root = monte_carlo.get(0);
+        assert!(root.is_root());
+        assert_eq!(root.visited_count, visited);
+        assert_eq!(monte_carlo.tree.state(0).to_string(), board.to_string());
+        assert!(monte_carlo.tree.nodes.len() < size);
+        for (i, node) in monte_carlo.tree.nodes.iter().enumerate() {
+            assert_eq!(node.index, i);
+            assert!(node
+                .children
+                .iter()
+                .all(|c| monte_carlo.tree.nodes[*c].parent == i));
+        }
+        monte_carlo.search_move();
+
+        // A position which is not below the root starts a new tree
+        let other = Board::new_default();
+        assert!(!monte_carlo.advance(&other));
+        assert_eq!(monte_carlo.tree.nodes.len(), 1);
+        assert_eq!(monte_carlo.get(0).player, 1);
+    }
+
+    #[test]
+    fn test_monte_carlo_engine_rules() {
+        // A fresh engine is rooted at the freestyle empty board, which must not
+        // stand for the empty board of an exact-five game
+        let options = EngineOptions {
+            threads: Some(1),
+            seed: Some(1),
+            ..EngineOptions::default()
+        };
+        let config = MonteCarloConfig::default();
+        let mut engine = MonteCarloEngine::new("monte_carlo", config, &options);
+        let mut board = Board::new_default();
+        board.exact_five = true;
+        engine.set_position(&board);
+        assert!(engine.monte.tree.state(0).exact_five);
+        board.place(7, 7, 1);
+        engine.set_position(&board);
+        engine.search(&SearchLimits::nodes(100));
+        assert!(engine.monte.tree.state(0).exact_five);
+
+        // The same stones under the freestyle rule start over
+        let mut freestyle = Board::new_default();
+        freestyle.place(7, 7, 1);
+        assert!(!engine.monte.advance(&freestyle));
+        assert!(!engine.monte.tree.state(0).exact_five);
+    }
+
+    #[test]
+    fn test_monte_carlo_no_move() {
+        // Nothing to play on a full board, nor after a five
+        let full = Board::new("12".repeat(9 * 9 / 2) + "1", 9, 9);
+        let mut won = Board::new_default();
+        for col in 0..5 {
+            won.place(0, col, 1);
+        }
+        for col in 0..4 {
+            won.place(10, col, 2);
+        }
+        for board in [full, won] {
+            for threads in [1, 4] {
+                let mut monte_carlo = MonteCarlo::new(board.clone(), board.next_player(), 100);
+                monte_carlo.set_threads(threads);
+                assert!(!monte_carlo.search().has_move(), "{} threads", threads);
+            }
+        }
+    }
+
+    #[test]
+    fn test_monte_carlo_parallel() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 400);
+        monte_carlo.set_threads(4);
+        let res = monte_carlo.search();
+        assert_eq!(res.stats.simulations, 400);
+        assert_eq!(res.stats.threads, 4);
+        let visits: u32 = std::iter::once(&monte_carlo.tree)
+            .chain(monte_carlo.helpers.iter())
+            .map(|t| t.nodes[0].visited_count)
+            .sum();
+        assert_eq!(visits, 400);
+        assert_eq!(monte_carlo.helpers.len(), 3);
+        let mv = res.best_move;
+        assert!((mv.x as i32 - 7).abs() <= 1 && (mv.y as i32 - 7).abs() <= 1);
+
+        // Every tree follows the game
+        board.place(mv.x, mv.y, 2);
+        board.place(0, 0, 1);
+        monte_carlo.advance(&board);
+        monte_carlo.search();
+        for tree in monte_carlo.helpers.iter() {
+            assert_eq!(tree.state(0).to_string(), board.to_string());
+        }
+    }
+
+    #[test]
+    fn test_monte_carlo_solver_win() {
+        let mut board = Board::new_default();
+        for (i, j) in [(0, 0), (0, 2), (0, 4), (0, 6), (14, 14)] {
+            board.place(i, j, 1);
+        }
+        for j in 3..7 {
+            board.place(7, j, 2);
+        }
+        let mut monte_carlo = MonteCarlo::new(board, 2, 3000);
+        let res = monte_carlo.search();
+        // The winning move ends the search at once
+        assert!(monte_carlo.tree.is_proven());
+        assert_eq!(monte_carlo.get(0).proof, Proof::Loss);
+        assert!(res.stats.simulations < 100);
+        let mv = res.best_move;
+        assert!((mv.x, mv.y) == (7, 2) || (mv.x, mv.y) == (7, 7));
+    }
+
+    #[test]
+    fn test_monte_carlo_solver_loss() {
+        let mut board = Board::new_default();
+        for j in 3..7 {
+            board.place(7, j, 1);
+        }
+        for (i, j) in [(0, 0), (0, 3), (0, 6)] {
+            board.place(i, j, 2);
+        }
+        // An open four can not be stopped
+        let mut monte_carlo = MonteCarlo::new(board, 2, 3000);
+        let res = monte_carlo.search();
+        assert_eq!(monte_carlo.get(0).proof, Proof::Win);
+        assert!(res.stats.simulations < 3000);
+        assert!(monte_carlo
+            .tree
+            .root_children()
+            .all(|c| c.proof == Proof::Loss));
+    }
+
+    #[test]
+    fn test_monte_carlo_limits() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 50);
+        let res = monte_carlo.search();
+        assert_eq!(res.stats.simulations, 50);
+        assert_eq!(monte_carlo.get(0).visited_count, 50);
+
+        // The time limit ends the search long before a million simulations
+        let limits = SearchLimits {
+            time_ms: Some(100),
+            nodes: Some(1_000_000),
+            depth: None,
+        };
+        let mut monte_carlo = MonteCarlo::new_with_limits(board, 2, limits);
+        let res = monte_carlo.search();
+        assert!(res.stats.simulations > 0 && res.stats.simulations < 1_000_000);
+        assert!(res.stats.elapsed_ms >= 100 && res.stats.elapsed_ms < 5000);
+        assert_eq!(res.stats.nodes, monte_carlo.tree.nodes.len() as u64);
+    }
+
+    #[test]
+    fn test_monte_carlo_rave() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 500);
+        monte_carlo.set_config(MonteCarloConfig::rave());
+        let mv = monte_carlo.search_move();
+        assert!((mv.x as i32 - 7).abs() <= 2 && (mv.y as i32 - 7).abs() <= 2);
+        // A simulation through a child also plays its move, so it counts for AMAF
+        assert!(monte_carlo
+            .tree
+            .root_children()
+            .all(|c| c.amaf_count >= c.visited_count));
+        assert!(monte_carlo
+            .tree
+            .root_children()
+            .any(|c| c.amaf_count > c.visited_count));
+
+        // The config survives a fresh tree
+        board.place(0, 0, 2);
+        board.place(14, 14, 1);
+        assert!(!monte_carlo.advance(&board));
+        assert_eq!(monte_carlo.config(), MonteCarloConfig::rave());
+
+        // Plain UCT does not keep AMAF statistics
+        let mut monte_carlo = MonteCarlo::new(board, 2, 200);
+        monte_carlo.search();
+        assert!(monte_carlo.tree.root_children().all(|c| c.amaf_count == 0));
+    }
+
+    #[test]
+    fn test_monte_carlo_seed() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(6, 8, 2);
+        board.place(8, 8, 1);
+        let search = |seed| {
+            let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 300);
+            monte_carlo.set_seed(seed);
+            let mv = monte_carlo.search_move();
+            let visits: Vec<u32> = monte_carlo
+                .tree
+                .root_children()
+                .map(|c| c.visited_count)
+                .collect();
+            (mv.x, mv.y, visits)
+        };
+        assert_eq!(search(11), search(11));
+        assert_ne!(search(11).2, search(12).2);
+    }
+
+    #[test]
+    fn test_monte_carlo_export() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut monte_carlo = MonteCarlo::new(board, 2, 300);
+        let mv = monte_carlo.search_move();
+        let options = ExportOptions {
+            max_depth: 2,
+            min_visits: 5,
+        };
+        let export = monte_carlo.export_tree(&options);
+        let root = &export.root;
+        assert_eq!(root.visits, 300);
+        assert!(root.pv);
+        assert_eq!(root.pv_moves()[0], (mv.x, mv.y));
+        assert!(root.children.iter().all(|c| c.visits >= 5));
+        assert!(root
+            .children
+            .iter()
+            .all(|c| c.children.iter().all(|g| g.children.is_empty())));
+        assert!(export.to_dot().contains("fillcolor=lightblue"));
+    }
+
+    #[test]
+    fn test_monte_carlo_puct() {
+        let mut board = Board::new_default();
+        for j in 6..9 {
+            board.place(7, j, 1);
+        }
+        board.place(6, 6, 2);
+        board.place(8, 8, 2);
+        let mut monte_carlo = MonteCarlo::new(board.clone(), 2, 500);
+        monte_carlo.set_config(MonteCarloConfig::puct());
+        let mv = monte_carlo.search_move();
+        // The open three has to be blocked
+        assert!((mv.x, mv.y) == (7, 5) || (mv.x, mv.y) == (7, 9));
+        assert!(monte_carlo.tree.root_children().all(|c| c.prior > 0.0));
+
+        // A policy which only likes the last generated move
+        struct LastMove;
+        impl PriorPolicy for LastMove {
+            fn priors(&self, _board: &Board, _player: u8, moves: &[Move]) -> Vec<f32> {
+                let mut priors = vec![0.0; moves.len()];
+                *priors.last_mut().unwrap() = 1.0;
+                priors
+            }
+        }
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let last = board.gen_ordered_moves_all(2).pop().unwrap();
+        let mut monte_carlo = MonteCarlo::new(board, 2, 20);
+        monte_carlo.set_config(MonteCarloConfig::puct());
+        monte_carlo.set_prior_policy(Arc::new(LastMove));
+        monte_carlo.search();
+        let first = monte_carlo.tree.root_children().next().unwrap();
+        assert_eq!(first.prior, 1.0);
+        assert_eq!(
+            (first.action.unwrap().x, first.action.unwrap().y),
+            (last.x, last.y)
+        );
+        assert!(monte_carlo.tree.root_children().count() < 8);
+    }
 }
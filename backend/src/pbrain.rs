@@ -0,0 +1,352 @@
+#![allow(dead_code)]
+// Piskvork / Gomocup `pbrain` protocol, the brain side
+use super::algo::{create_engine, Engine, EngineOptions, UnknownEngine};
+use super::board::*;
+use super::utils::*;
+
+// Thinking time per move before the manager sends `INFO timeout_turn`
+const DEFAULT_TURN_MS: u64 = 5000;
+// Moves the remaining match time is shared between
+const MATCH_MOVES_LEFT: u64 = 25;
+// Time kept back for reading the command and writing the answer
+const SAFETY_MARGIN_MS: u64 = 50;
+
+/// Values sent with `INFO`, times are in milliseconds and 0 means no limit
+/// (for `timeout_turn` it means playing as fast as possible)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrainInfo {
+    pub timeout_turn: u64,
+    pub timeout_match: u64,
+    pub time_left: Option<u64>,
+    pub max_memory: u64,
+    /// Bit mask: 1 exactly five, 2 continuous game, 4 renju, 8 caro
+    pub rule: u32,
+}
+
+impl Default for BrainInfo {
+    fn default() -> Self {
+        Self {
+            timeout_turn: DEFAULT_TURN_MS,
+            timeout_match: 0,
+            time_left: None,
+            max_memory: 0,
+            rule: 0,
+        }
+    }
+}
+
+impl BrainInfo {
+    pub fn exact_five(&self) -> bool {
+        self.rule & 1 != 0
+    }
+
+    /// Thinking time of the next move
+    pub fn move_time_ms(&self) -> u64 {
+        let mut time = self.timeout_turn;
+        if self.timeout_match > 0 {
+            let left = self.time_left.unwrap_or(self.timeout_match);
+            time = time.min(left / MATCH_MOVES_LEFT);
+        }
+        time.saturating_sub(SAFETY_MARGIN_MS).max(1)
+    }
+}
+
+/* The state of one brain: the stones, the engine and the manager's settings.
+ * Commands are fed line by line to `handle`, which returns the lines to answer.
+ * Stones are kept as own and opponent's moves, the colors on the engine's board
+ * follow from who moves next.
+ */
+pub struct Brain {
+    algo: String,
+    options: EngineOptions,
+    engine: Box<dyn Engine>,
+    info: BrainInfo,
+    width: usize,
+    height: usize,
+    // (x, y, who) with who 1 for own stones and 2 for the opponent's, in move order
+    stones: Option<Vec<(usize, usize, u8)>>,
+    // Stones of a `BOARD` command until `DONE`
+    pending: Option<Vec<(usize, usize, u8)>>,
+    ended: bool,
+}
+
+impl Brain {
+    pub fn new(algo: &str, options: &EngineOptions) -> Result<Self, UnknownEngine> {
+        Ok(Self {
+            algo: algo.to_string(),
+            options: options.clone(),
+            engine: create_engine(algo, options)?,
+            info: BrainInfo::default(),
+            width: 0,
+            height: 0,
+            stones: None,
+            pending: None,
+            ended: false,
+        })
+    }
+
+    pub fn info(&self) -> &BrainInfo {
+        &self.info
+    }
+
+    /// Whether `END` was received
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Answer to one command line, empty when the command has no answer
+    pub fn handle(&mut self, line: &str) -> Vec<String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return vec![];
+        }
+        if self.pending.is_some() {
+            return self.board_line(line);
+        }
+        let (command, args) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, ""),
+        };
+        match command.to_uppercase().as_str() {
+            "START" => {
+                let size = args.parse().unwrap_or(0);
+                self.start(size, size)
+            }
+            "RECTSTART" => match parse_pair(args) {
+                Some((w, h)) => self.start(w, h),
+                None => vec![format!("ERROR bad size {}", args)],
+            },
+            "RESTART" => match self.stones {
+                Some(_) => self.start(self.width, self.height),
+                None => vec!["ERROR no game started".to_string()],
+            },
+            "BEGIN" => self.play(),
+            "TURN" => match self.add_stone(args, 2) {
+                Ok(()) => self.play(),
+                Err(e) => vec![e],
+            },
+            "TAKEBACK" => match self.take_back(args) {
+                Ok(()) => vec!["OK".to_string()],
+                Err(e) => vec![e],
+            },
+            "BOARD" => match self.stones {
+                Some(_) => {
+                    self.pending = Some(vec![]);
+                    vec![]
+                }
+                None => vec!["ERROR no game started".to_string()],
+            },
+            "INFO" => self.set_info(args),
+            "ABOUT" => vec![format!(
+                "name=\"gomoku-{}\", version=\"{}\", author=\"chenyukang\"",
+                self.algo,
+                env!("CARGO_PKG_VERSION")
+            )],
+            "END" => {
+                self.ended = true;
+                vec![]
+            }
+            _ => vec![format!("UNKNOWN {}", command)],
+        }
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> Vec<String> {
+        let sizes = 5..=MAX_BOARD_SIZE;
+        if !sizes.contains(&width) || !sizes.contains(&height) {
+            return vec![format!("ERROR unsupported size {}x{}", width, height)];
+        }
+        self.width = width;
+        self.height = height;
+        self.stones = Some(vec![]);
+        self.engine.new_game();
+        vec!["OK".to_string()]
+    }
+
+    fn set_info(&mut self, args: &str) -> Vec<String> {
+        let (key, value) = match args.find(' ') {
+            Some(i) => (&args[..i], args[i + 1..].trim()),
+            None => (args, ""),
+        };
+        let number = value.parse::<u64>().ok();
+        match (key, number) {
+            ("timeout_turn", Some(v)) => self.info.timeout_turn = v,
+            ("timeout_match", Some(v)) => self.info.timeout_match = v,
+            ("time_left", Some(v)) => self.info.time_left = Some(v),
+            ("max_memory", Some(v)) => self.info.max_memory = v,
+            ("rule", Some(v)) => {
+                self.info.rule = v as u32;
+                if self.info.rule & (4 | 8) != 0 {
+                    return vec![format!(
+                        "MESSAGE rule {} is not supported, renju and caro restrictions are ignored",
+                        v
+                    )];
+                }
+            }
+            // game_type, evaluate, folder and later keys are not used
+            _ => (),
+        }
+        vec![]
+    }
+
+    fn board_line(&mut self, line: &str) -> Vec<String> {
+        if line.eq_ignore_ascii_case("DONE") {
+            let stones = self.pending.take().unwrap();
+            self.stones = Some(vec![]);
+            for (x, y, who) in stones {
+                if let Err(e) = self.place(x, y, who) {
+                    return vec![e];
+                }
+            }
+            return self.play();
+        }
+        let fields: Vec<Option<usize>> = line.split(',').map(|f| f.trim().parse().ok()).collect();
+        match fields.as_slice() {
+            // 3 marks a stone of a finished line in a continuous game, it only takes the cell
+            [Some(x), Some(y), Some(who @ 1..=3)] => {
+                let who = if *who == 1 { 1 } else { 2 };
+                self.pending.as_mut().unwrap().push((*x, *y, who));
+                vec![]
+            }
+            _ => vec![format!("ERROR bad board line {}", line)],
+        }
+    }
+
+    fn add_stone(&mut self, args: &str, who: u8) -> Result<(), String> {
+        let (x, y) = parse_pair(args).ok_or(format!("ERROR bad move {}", args))?;
+        self.place(x, y, who)
+    }
+
+    fn place(&mut self, x: usize, y: usize, who: u8) -> Result<(), String> {
+        let (width, height) = (self.width, self.height);
+        let stones = self.stones.as_mut().ok_or("ERROR no game started")?;
+        if x >= width || y >= height {
+            return Err(format!("ERROR move {},{} is outside the board", x, y));
+        }
+        if stones.iter().any(|&(sx, sy, _)| (sx, sy) == (x, y)) {
+            return Err(format!("ERROR {},{} is not empty", x, y));
+        }
+        stones.push((x, y, who));
+        Ok(())
+    }
+
+    fn take_back(&mut self, args: &str) -> Result<(), String> {
+        let (x, y) = parse_pair(args).ok_or(format!("ERROR bad move {}", args))?;
+        let stones = self.stones.as_mut().ok_or("ERROR no game started")?;
+        match stones.iter().rposition(|&(sx, sy, _)| (sx, sy) == (x, y)) {
+            Some(i) => {
+                stones.remove(i);
+                Ok(())
+            }
+            None => Err(format!("ERROR {},{} is empty", x, y)),
+        }
+    }
+
+    /// The position with the brain to move, `x` is the column and `y` the row
+    pub fn board(&self) -> Option<Board> {
+        let stones = self.stones.as_ref()?;
+        let own_count = stones.iter().filter(|s| s.2 == 1).count();
+        // The side with fewer stones moves, it is black when both have as many
+        let own = if own_count < stones.len() - own_count {
+            2
+        } else {
+            1
+        };
+        let input = "0".repeat(self.width * self.height);
+        let mut board = Board::new(input, self.width, self.height);
+        board.exact_five = self.info.exact_five();
+        for &(x, y, who) in stones {
+            let player = if who == 1 { own } else { cfg::opponent(own) };
+            board.place(y, x, player);
+        }
+        Some(board)
+    }
+
+    fn play(&mut self) -> Vec<String> {
+        let board = match self.board() {
+            Some(board) => board,
+            None => return vec!["ERROR no game started".to_string()],
+        };
+        if board.empty_cells_count() == 0 {
+            return vec!["ERROR the board is full".to_string()];
+        }
+        let options = EngineOptions {
+            time_ms: Some(self.options.time_ms.unwrap_or(self.info.move_time_ms())),
+            ..self.options.clone()
+        };
+        self.engine.set_position(&board);
+        let res = self.engine.search(&options.limits());
+        if !res.has_move() {
+            return vec!["ERROR no move found".to_string()];
+        }
+        let (x, y) = (res.best_move.y, res.best_move.x);
+        if let Err(e) = self.place(x, y, 1) {
+            return vec![e];
+        }
+        vec![format!("{},{}", x, y)]
+    }
+}
+
+fn parse_pair(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.split(',').map(|p| p.trim().parse::<usize>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(a)), Some(Ok(b)), None) => Some((a, b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(brain: &mut Brain, line: &str) -> Vec<String> {
+        brain.handle(line)
+    }
+
+    #[test]
+    fn test_brain_protocol() {
+        let mut brain = Brain::new("one_ply", &EngineOptions::default()).unwrap();
+        assert!(send(&mut brain, "BEGIN")[0].starts_with("ERROR"));
+        assert!(send(&mut brain, "START 3")[0].starts_with("ERROR"));
+        assert_eq!(send(&mut brain, "START 20"), vec!["OK"]);
+        assert!(send(&mut brain, "INFO timeout_turn 1000").is_empty());
+        assert!(send(&mut brain, "INFO timeout_match 100000").is_empty());
+        assert!(send(&mut brain, "INFO time_left 20000").is_empty());
+        assert!(send(&mut brain, "INFO rule 1").is_empty());
+        assert_eq!(
+            brain.info().move_time_ms(),
+            20000 / MATCH_MOVES_LEFT - SAFETY_MARGIN_MS
+        );
+        assert!(brain.info().exact_five());
+        assert!(send(&mut brain, "INFO rule 4")[0].starts_with("MESSAGE"));
+        assert!(send(&mut brain, "ABOUT")[0].contains("name=\"gomoku-one_ply\""));
+        assert_eq!(send(&mut brain, "DANCE"), vec!["UNKNOWN DANCE"]);
+
+        // The first move is in the 5x5 square at the center of the 20x20 board
+        let first = send(&mut brain, "BEGIN");
+        let (x, y) = parse_pair(&first[0]).unwrap();
+        assert!((8..=12).contains(&x) && (8..=12).contains(&y));
+        let reply = send(&mut brain, "TURN 0,0");
+        assert!(parse_pair(&reply[0]).is_some());
+        assert!(send(&mut brain, "TURN 0,0")[0].starts_with("ERROR"));
+        assert!(send(&mut brain, "TURN 20,0")[0].starts_with("ERROR"));
+        assert_eq!(
+            send(&mut brain, &format!("TAKEBACK {}", reply[0])),
+            vec!["OK"]
+        );
+        assert_eq!(send(&mut brain, "TAKEBACK 0,0"), vec!["OK"]);
+        assert!(send(&mut brain, "TAKEBACK 0,0")[0].starts_with("ERROR"));
+
+        // The opponent's four is blocked, x is the column and y the row
+        assert_eq!(send(&mut brain, "RESTART"), vec!["OK"]);
+        assert!(send(&mut brain, "BOARD").is_empty());
+        for line in ["5,5,2", "6,5,2", "7,5,2", "8,5,2", "4,5,1", "10,10,1"] {
+            assert!(send(&mut brain, line).is_empty());
+        }
+        assert_eq!(send(&mut brain, "DONE"), vec!["9,5"]);
+        // The brain has fewer stones, so it plays white
+        assert_eq!(brain.board().unwrap().get(5, 9), Some(2));
+
+        send(&mut brain, "END");
+        assert!(brain.ended());
+    }
+}
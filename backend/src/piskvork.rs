@@ -0,0 +1,294 @@
+#![allow(dead_code)]
+// Piskvork / Gomocup `pbrain` protocol, the manager side - external brains as engines
+use super::algo::Engine;
+use super::board::*;
+use super::search::{SearchControl, SearchLimits, SearchResult, SearchStats, StopToken, Timer};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Engine names starting with this run an external brain, e.g. `pbrain:./pbrain-foo --fast`
+pub const EXTERNAL_PREFIX: &str = "pbrain:";
+
+// Thinking time per move when the search has no time limit
+const DEFAULT_TURN_MS: u64 = 5000;
+// Time a brain gets on top of its turn time, e.g. for starting up
+const GRACE_MS: u64 = 2000;
+// Time to answer commands which do not search
+const COMMAND_TIMEOUT_MS: u64 = 5000;
+
+/// Something went wrong talking to a brain
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrainError {
+    Spawn(String),
+    Timeout(String),
+    Exited,
+    Protocol(String),
+}
+
+impl std::fmt::Display for BrainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BrainError::Spawn(e) => write!(f, "failed to start the brain: {}", e),
+            BrainError::Timeout(command) => write!(f, "no answer to {} in time", command),
+            BrainError::Exited => write!(f, "the brain exited"),
+            BrainError::Protocol(answer) => write!(f, "unexpected answer: {}", answer),
+        }
+    }
+}
+
+impl std::error::Error for BrainError {}
+
+// A running brain process, its answers are read on a thread so they can time out
+struct BrainProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl BrainProcess {
+    fn spawn(command: &[String]) -> Result<Self, BrainError> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| BrainError::Spawn("empty command".to_string()))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| BrainError::Spawn(format!("{}: {}", program, e)))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), BrainError> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| BrainError::Exited)
+    }
+
+    // The next answer, MESSAGE, DEBUG and UNKNOWN lines are skipped
+    fn answer(&mut self, command: &str, timeout_ms: u64) -> Result<String, BrainError> {
+        let timer = Timer::start();
+        loop {
+            let left = timeout_ms.saturating_sub(timer.elapsed_ms());
+            match self.lines.recv_timeout(Duration::from_millis(left)) {
+                Ok(line) => {
+                    let line = line.trim().to_string();
+                    let upper = line.to_uppercase();
+                    if line.is_empty()
+                        || ["MESSAGE", "DEBUG", "UNKNOWN"]
+                            .iter()
+                            .any(|p| upper.starts_with(p))
+                    {
+                        continue;
+                    }
+                    if upper.starts_with("ERROR") {
+                        return Err(BrainError::Protocol(line));
+                    }
+                    return Ok(line);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(BrainError::Timeout(command.to_string()))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(BrainError::Exited),
+            }
+        }
+    }
+
+    fn expect_ok(&mut self, command: &str) -> Result<(), BrainError> {
+        self.send(command)?;
+        match self.answer(command, COMMAND_TIMEOUT_MS)? {
+            ok if ok.eq_ignore_ascii_case("OK") => Ok(()),
+            other => Err(BrainError::Protocol(other)),
+        }
+    }
+}
+
+impl Drop for BrainProcess {
+    fn drop(&mut self) {
+        let _ = self.send("END");
+        let timer = Timer::start();
+        while timer.elapsed_ms() < 200 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/* An external brain speaking the pbrain protocol, used like the engines of
+ * this crate. Each search sends the whole position with BOARD, so positions
+ * need not follow each other. The time limit is passed on as timeout_turn,
+ * node and depth limits are not. A brain which fails or does not answer in
+ * time is restarted on the next search, the failed search returns
+ * `SearchResult::no_move`, see `last_error`.
+ */
+pub struct ExternalEngine {
+    name: String,
+    command: Vec<String>,
+    process: Option<BrainProcess>,
+    // Board size the running brain was started with
+    started: Option<(usize, usize)>,
+    board: Board,
+    control: SearchControl,
+    last_error: Option<BrainError>,
+}
+
+impl ExternalEngine {
+    /// `command` is the program and its arguments separated by whitespace
+    pub fn new(name: &str, command: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.split_whitespace().map(String::from).collect(),
+            process: None,
+            started: None,
+            board: Board::new_default(),
+            control: SearchControl::default(),
+            last_error: None,
+        }
+    }
+
+    /// Why the last search failed, `None` when it succeeded
+    pub fn last_error(&self) -> Option<&BrainError> {
+        self.last_error.as_ref()
+    }
+
+    /// The brain's `ABOUT` answer
+    pub fn about(&mut self) -> Result<String, BrainError> {
+        let process = self.process()?;
+        process.send("ABOUT")?;
+        process.answer("ABOUT", COMMAND_TIMEOUT_MS)
+    }
+
+    fn process(&mut self) -> Result<&mut BrainProcess, BrainError> {
+        if self.process.is_none() {
+            self.process = Some(BrainProcess::spawn(&self.command)?);
+            self.started = None;
+        }
+        Ok(self.process.as_mut().unwrap())
+    }
+
+    // Start a game of the board's size and pass on the turn time and rule
+    fn set_up(&mut self, turn_ms: u64) -> Result<(), BrainError> {
+        let size = (self.board.width, self.board.height);
+        let exact_five = self.board.exact_five;
+        self.process()?;
+        let process = self.process.as_mut().unwrap();
+        if self.started != Some(size) {
+            if size.0 == size.1 {
+                process.expect_ok(&format!("START {}", size.0))?;
+            } else {
+                process.expect_ok(&format!("RECTSTART {},{}", size.0, size.1))?;
+            }
+            self.started = Some(size);
+        }
+        process.send(&format!("INFO timeout_turn {}", turn_ms))?;
+        process.send("INFO timeout_match 0")?;
+        process.send(&format!("INFO rule {}", exact_five as u8))?;
+        Ok(())
+    }
+
+    fn play(&mut self, turn_ms: u64) -> Result<(usize, usize), BrainError> {
+        self.set_up(turn_ms)?;
+        let board = self.board.clone();
+        let own = board.next_player();
+        let process = self.process()?;
+        process.send("BOARD")?;
+        for row in 0..board.height {
+            for col in 0..board.width {
+                match board.get(row as i32, col as i32) {
+                    Some(0) | None => (),
+                    Some(p) => {
+                        let who = if p == own { 1 } else { 2 };
+                        process.send(&format!("{},{},{}", col, row, who))?;
+                    }
+                }
+            }
+        }
+        process.send("DONE")?;
+        let answer = process.answer("BOARD", turn_ms + GRACE_MS)?;
+        let cell: Vec<Option<usize>> = answer.split(',').map(|v| v.trim().parse().ok()).collect();
+        match cell.as_slice() {
+            [Some(x), Some(y)] if board.get(*y as i32, *x as i32) == Some(0) => Ok((*y, *x)),
+            _ => Err(BrainError::Protocol(answer)),
+        }
+    }
+}
+
+impl Engine for ExternalEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) {
+        if let Some(process) = self.process.as_mut() {
+            if process.expect_ok("RESTART").is_err() {
+                self.process = None;
+            }
+        }
+        self.board = Board::new_default();
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    // The protocol has no way to interrupt a brain, stopping has no effect
+    fn stop_token(&self) -> StopToken {
+        self.control.stop.clone()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let timer = Timer::start();
+        let turn_ms = limits.time_ms.unwrap_or(DEFAULT_TURN_MS);
+        let (row, col) = match self.play(turn_ms) {
+            Ok(cell) => {
+                self.last_error = None;
+                cell
+            }
+            Err(e) => {
+                eprintln!("{}: {}", self.name, e);
+                // Start over with a fresh process next time
+                self.process = None;
+                self.last_error = Some(e);
+                let stats = SearchStats {
+                    elapsed_ms: timer.elapsed_ms(),
+                    ..SearchStats::default()
+                };
+                return SearchResult::no_move(stats, *limits);
+            }
+        };
+        SearchResult {
+            best_move: Move::new(row, col, 1, 0),
+            stats: SearchStats {
+                elapsed_ms: timer.elapsed_ms(),
+                ..SearchStats::default()
+            },
+            limits: *limits,
+        }
+    }
+}
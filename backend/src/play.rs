@@ -0,0 +1,396 @@
+#![allow(dead_code)]
+// Play against an engine in the terminal - moves in algebraic notation like `h8`
+use super::algo::{create_engine_with_external, Engine, EngineOptions, UnknownEngine};
+use super::board::*;
+use super::ponder::Ponderer;
+use std::io::{self, BufRead, Write};
+
+/// Setting up a game of `gomoku play`
+#[derive(Debug, Clone)]
+pub struct PlayConfig {
+    pub engine: String,
+    pub options: EngineOptions,
+    /// The player of the human, 1 is X and moves first
+    pub human: u8,
+    pub width: usize,
+    pub height: usize,
+    /// Only a line of exactly five wins
+    pub exact_five: bool,
+    /// Let the engine think on the human's time, see `Ponderer`
+    pub ponder: bool,
+}
+
+impl Default for PlayConfig {
+    fn default() -> Self {
+        Self {
+            engine: "hybrid".to_string(),
+            options: EngineOptions::default(),
+            human: 1,
+            width: 15,
+            height: 15,
+            exact_five: false,
+            ponder: false,
+        }
+    }
+}
+
+/// What the human typed at the prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Move(usize, usize),
+    Undo,
+    Hint,
+    Help,
+    Quit,
+}
+
+const HELP: &str = "Type a move like h8 (column letter, row number), \
+                    `undo` to take back your last move, `hint` for a suggestion \
+                    and `quit` to leave.";
+
+/// The cell named like `h8`, the columns are lettered from `a` and the rows numbered
+/// from 1 at the bottom, as printed by `Board::print_algebraic`
+pub fn parse_move(text: &str, width: usize, height: usize) -> Option<(usize, usize)> {
+    let text = text.trim().to_lowercase();
+    let mut chars = text.chars();
+    let letter = chars.next()?;
+    if !letter.is_ascii_lowercase() {
+        return None;
+    }
+    let col = (letter as u8 - b'a') as usize;
+    let number: usize = chars.as_str().parse().ok()?;
+    if col >= width || number == 0 || number > height {
+        return None;
+    }
+    Some((height - number, col))
+}
+
+/// The algebraic name of a cell, the reverse of `parse_move`
+pub fn format_move(row: usize, col: usize, height: usize) -> String {
+    format!("{}{}", (b'a' + col as u8) as char, height - row)
+}
+
+pub fn parse_command(text: &str, width: usize, height: usize) -> Option<Command> {
+    match text.trim().to_lowercase().as_str() {
+        "u" | "undo" => Some(Command::Undo),
+        "hint" => Some(Command::Hint),
+        "?" | "help" => Some(Command::Help),
+        "q" | "quit" | "exit" => Some(Command::Quit),
+        other => parse_move(other, width, height).map(|(row, col)| Command::Move(row, col)),
+    }
+}
+
+fn stone(player: u8) -> char {
+    if player == 1 {
+        'X'
+    } else {
+        'O'
+    }
+}
+
+/* A game between the human and an engine. The hints come from a second
+ * engine of the same kind at full strength, so a low level does not give
+ * weak advice. Taking back a move also takes back the engine's answer.
+ * With `PlayConfig::ponder` the engine searches the reply it expects while
+ * the human thinks.
+ */
+pub struct PlayGame {
+    config: PlayConfig,
+    engine: Ponderer,
+    hint_engine: Option<Box<dyn Engine>>,
+    board: Board,
+    history: Vec<(usize, usize)>,
+}
+
+impl PlayGame {
+    pub fn new(config: &PlayConfig) -> Result<Self, UnknownEngine> {
+        let mut engine = create_engine_with_external(&config.engine, &config.options)?;
+        engine.new_game();
+        let mut board = Board::new(
+            "0".repeat(config.width * config.height),
+            config.width,
+            config.height,
+        );
+        board.exact_five = config.exact_five;
+        Ok(Self {
+            config: config.clone(),
+            engine: Ponderer::with_engine(engine, config.options.limits()),
+            hint_engine: None,
+            board,
+            history: vec![],
+        })
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn human(&self) -> u8 {
+        self.config.human
+    }
+
+    pub fn is_human_turn(&self) -> bool {
+        self.board.next_player() == self.config.human
+    }
+
+    /// The winner, `Some(0)` for a full board without one, `None` while the game goes on
+    pub fn result(&self) -> Option<u8> {
+        match self.board.any_winner() {
+            Some(p) => Some(p),
+            None if self.board.empty_cells_count() == 0 => Some(0),
+            None => None,
+        }
+    }
+
+    pub fn human_move(&mut self, row: usize, col: usize) -> Result<(), String> {
+        if !self.is_human_turn() {
+            return Err("it is not your turn".to_string());
+        }
+        self.place(row, col)
+    }
+
+    /// Let the engine search and play its move, then ponder when asked to
+    pub fn engine_move(&mut self) -> Result<(usize, usize), String> {
+        let res = self.engine.best_move(&self.board);
+        if !res.has_move() {
+            return Err(format!("{} has no move, you win", self.engine.name()));
+        }
+        let mv = res.best_move;
+        self.place(mv.x, mv.y)
+            .map_err(|e| format!("{} failed to move: {}", self.engine.name(), e))?;
+        if self.config.ponder && self.result().is_none() {
+            self.engine.start(&self.board);
+        }
+        Ok((mv.x, mv.y))
+    }
+
+    /// Ponder hits and misses of the engine so far
+    pub fn ponder_stats(&self) -> (u32, u32) {
+        (self.engine.hits, self.engine.misses)
+    }
+
+    /// A suggestion for the human's move
+    pub fn hint(&mut self) -> Result<(usize, usize), String> {
+        if self.result().is_some() {
+            return Err("the game is over".to_string());
+        }
+        if self.hint_engine.is_none() {
+            let options = EngineOptions {
+                level: None,
+                ..self.config.options.clone()
+            };
+            let engine = create_engine_with_external(&self.config.engine, &options)
+                .map_err(|e| e.to_string())?;
+            self.hint_engine = Some(engine);
+        }
+        let engine = self.hint_engine.as_mut().unwrap();
+        engine.set_position(&self.board);
+        let res = engine.search(&self.config.options.limits());
+        let mv = res.best_move;
+        if !res.has_move() || self.board.get(mv.x as i32, mv.y as i32) != Some(0) {
+            return Err(format!("{} has no suggestion", engine.name()));
+        }
+        Ok((mv.x, mv.y))
+    }
+
+    /// Take back moves until it is the human's turn again after one of their moves
+    pub fn undo(&mut self) -> Result<(), String> {
+        let count = if self.is_human_turn() { 2 } else { 1 };
+        // The engine's opening move stays on the board
+        if self.history.len() < count {
+            return Err("no move of yours to take back".to_string());
+        }
+        for _ in 0..count {
+            let (row, col) = self.history.pop().unwrap();
+            self.board.place(row, col, 0);
+        }
+        // Mark the last move again
+        if let Some(&(row, col)) = self.history.last() {
+            let player = self.board.get(row as i32, col as i32).unwrap();
+            self.board.place(row, col, player);
+        }
+        Ok(())
+    }
+
+    fn place(&mut self, row: usize, col: usize) -> Result<(), String> {
+        if self.result().is_some() {
+            return Err("the game is over".to_string());
+        }
+        if self.board.get(row as i32, col as i32) != Some(0) {
+            let name = format_move(row, col, self.board.height);
+            return Err(format!("{} is not an empty cell", name));
+        }
+        let player = self.board.next_player();
+        self.board.place(row, col, player);
+        self.history.push((row, col));
+        Ok(())
+    }
+}
+
+/// Play a game on the terminal until it is over, the human quits or stdin is closed
+pub fn run(config: &PlayConfig) {
+    if config.width > 26 || config.width < 5 || config.height < 5 {
+        eprintln!("the board must be 5 to 26 cells wide and at least 5 high");
+        return;
+    }
+    let mut game = match PlayGame::new(config) {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let (width, height) = (config.width, config.height);
+    println!(
+        "You play {} against {}{}. {}",
+        stone(config.human),
+        config.engine,
+        config
+            .options
+            .level
+            .map(|l| format!(" at level {}", l))
+            .unwrap_or_default(),
+        HELP
+    );
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if game.result().is_none() && !game.is_human_turn() {
+            match game.engine_move() {
+                Ok((row, col)) => {
+                    println!("{} plays {}", config.engine, format_move(row, col, height))
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    break;
+                }
+            }
+            continue;
+        }
+        println!();
+        game.board().print_algebraic();
+        match game.result() {
+            Some(0) => {
+                println!("The board is full, it is a draw.");
+                break;
+            }
+            Some(p) if p == config.human => {
+                println!("{} wins, well played!", stone(p));
+                break;
+            }
+            Some(p) => {
+                println!("{} wins, {} beat you.", stone(p), config.engine);
+                break;
+            }
+            None => (),
+        }
+        print!("{} to move > ", stone(config.human));
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let res = match parse_command(&line, width, height) {
+            Some(Command::Move(row, col)) => game.human_move(row, col),
+            Some(Command::Undo) => game.undo(),
+            Some(Command::Hint) => game
+                .hint()
+                .map(|(row, col)| println!("Try {}", format_move(row, col, height))),
+            Some(Command::Help) => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            Some(Command::Quit) => break,
+            None => Err(format!("unknown move or command: {}", line.trim())),
+        };
+        if let Err(e) = res {
+            println!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algebraic_notation() {
+        assert_eq!(parse_move("h8", 15, 15), Some((7, 7)));
+        assert_eq!(parse_move(" A1 ", 15, 15), Some((14, 0)));
+        assert_eq!(parse_move("o15", 15, 15), Some((0, 14)));
+        assert_eq!(parse_move("p1", 15, 15), None);
+        assert_eq!(parse_move("a0", 15, 15), None);
+        assert_eq!(parse_move("a16", 15, 15), None);
+        assert_eq!(parse_move("8h", 15, 15), None);
+        assert_eq!(format_move(14, 0, 15), "a1");
+        assert_eq!(format_move(0, 14, 15), "o15");
+        assert_eq!(parse_command("undo", 15, 15), Some(Command::Undo));
+        assert_eq!(parse_command("j10", 15, 15), Some(Command::Move(5, 9)));
+        assert_eq!(parse_command("resign", 15, 15), None);
+    }
+
+    #[test]
+    fn test_play_game() {
+        let config = PlayConfig {
+            engine: "one_ply".to_string(),
+            options: EngineOptions {
+                seed: Some(3),
+                ..EngineOptions::default()
+            },
+            human: 2,
+            ..PlayConfig::default()
+        };
+        let mut game = PlayGame::new(&config).unwrap();
+        assert!(!game.is_human_turn());
+        assert!(game.human_move(7, 7).is_err());
+        let first = game.engine_move().unwrap();
+        assert!(game.undo().is_err());
+        assert!(game.human_move(first.0, first.1).is_err());
+
+        let hint = game.hint().unwrap();
+        assert_eq!(game.board().get(hint.0 as i32, hint.1 as i32), Some(0));
+        game.human_move(hint.0, hint.1).unwrap();
+        game.engine_move().unwrap();
+        assert_eq!(game.board().total_moves(), 3);
+        game.undo().unwrap();
+        assert_eq!(game.board().total_moves(), 1);
+        assert!(game.is_human_turn());
+
+        // With pondering the engine expects the best reply, playing it is a hit
+        let mut game = PlayGame::new(&PlayConfig {
+            engine: "minimax".to_string(),
+            options: EngineOptions {
+                threads: Some(1),
+                depth: Some(2),
+                ..EngineOptions::default()
+            },
+            ponder: true,
+            ..config.clone()
+        })
+        .unwrap();
+        game.engine_move().unwrap();
+        let mut expected = game.board().clone();
+        let reply = expected.gen_ordered_moves_all(1)[0];
+        game.human_move(reply.x, reply.y).unwrap();
+        game.engine_move().unwrap();
+        game.human_move(0, 0).unwrap();
+        game.engine_move().unwrap();
+        assert_eq!(game.ponder_stats(), (1, 1));
+
+        // The engine completes its four
+        let mut game = PlayGame::new(&PlayConfig { human: 1, ..config }).unwrap();
+        for col in 3..7 {
+            game.place(0, col).unwrap();
+            game.place(10, col).unwrap();
+        }
+        game.human_move(14, 14).unwrap();
+        assert_eq!(game.result(), None);
+        game.engine_move().unwrap();
+        assert_eq!(game.result(), Some(2));
+        assert!(game.hint().is_err());
+        assert!(game.human_move(0, 0).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+#![allow(dead_code)]
+// Policies guiding the MonteCarlo search
+use super::board::*;
+use super::rng::GameRng;
+use super::utils::*;
+
+/// Prior probabilities of the moves of a position, used by PUCT selection.
+/// A trained policy network can be dropped in by implementing this trait.
+pub trait PriorPolicy: Send + Sync {
+    /// One prior for every move of `moves`, in the same order, summing to 1
+    fn priors(&self, board: &Board, player: u8, moves: &[Move]) -> Vec<f32>;
+}
+
+/// Softmax over the `eval_pos` scores computed by `gen_ordered_moves_all`
+pub struct HeuristicPrior {
+    /// Higher values flatten the distribution, a threat (score >= 1000) takes
+    /// nearly all of the mass with the default of 100
+    pub temperature: f32,
+}
+
+impl Default for HeuristicPrior {
+    fn default() -> Self {
+        Self { temperature: 100.0 }
+    }
+}
+
+impl PriorPolicy for HeuristicPrior {
+    fn priors(&self, _board: &Board, _player: u8, moves: &[Move]) -> Vec<f32> {
+        softmax(moves.iter().map(|mv| mv.score as f32 / self.temperature))
+    }
+}
+
+/// Picks the moves of a rollout
+pub trait RolloutPolicy: Send + Sync {
+    /// One of `moves`, which come from `gen_ordered_moves_all` and are ordered
+    /// by score, best first. `None` ends the rollout as a draw. All randomness
+    /// comes from `rng`, so a seeded search can be replayed.
+    fn choose(&self, board: &Board, player: u8, moves: &[Move], rng: &mut GameRng) -> Option<Move>;
+
+    /// Moves played before the rollout is cut off, `None` to use the
+    /// `rollout_depth` of the search config
+    fn max_depth(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Any of the candidate moves with the same probability
+pub struct UniformRollout;
+
+impl RolloutPolicy for UniformRollout {
+    fn choose(
+        &self,
+        _board: &Board,
+        _player: u8,
+        moves: &[Move],
+        rng: &mut GameRng,
+    ) -> Option<Move> {
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[rng.below(moves.len())])
+    }
+}
+
+/// The best scored move, but a random one with probability `epsilon`
+pub struct EpsilonGreedyRollout {
+    pub epsilon: f64,
+}
+
+impl RolloutPolicy for EpsilonGreedyRollout {
+    fn choose(&self, board: &Board, player: u8, moves: &[Move], rng: &mut GameRng) -> Option<Move> {
+        if rng.chance(self.epsilon) {
+            return UniformRollout.choose(board, player, moves, rng);
+        }
+        HeuristicRollout.choose(board, player, moves, rng)
+    }
+}
+
+/// A random move among the moves tied for the best score
+pub struct HeuristicRollout;
+
+impl RolloutPolicy for HeuristicRollout {
+    fn choose(
+        &self,
+        _board: &Board,
+        _player: u8,
+        moves: &[Move],
+        rng: &mut GameRng,
+    ) -> Option<Move> {
+        if moves.is_empty() {
+            return None;
+        }
+        let ties = moves
+            .iter()
+            .take_while(|mv| {
+                mv.score == moves[0].score && mv.original_score == moves[0].original_score
+            })
+            .count();
+        Some(moves[rng.below(ties)])
+    }
+}
+
+/// Heuristic moves for only `depth` moves, the rest of the game is left to
+/// the static evaluation
+pub struct TruncatedRollout {
+    pub depth: usize,
+}
+
+impl RolloutPolicy for TruncatedRollout {
+    fn choose(&self, board: &Board, player: u8, moves: &[Move], rng: &mut GameRng) -> Option<Move> {
+        HeuristicRollout.choose(board, player, moves, rng)
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        Some(self.depth)
+    }
+}
+
+/// Winner of a rollout cut off before the end of the game, `player` to move.
+/// A side counts as the winner when its `eval_all` score is well ahead of the
+/// other side's, otherwise the game is scored as a draw.
+pub fn static_winner(board: &mut Board, player: u8) -> Option<u8> {
+    let opponent = cfg::opponent(player);
+    let mine = board.eval_all(player);
+    let theirs = board.eval_all(opponent);
+    // The side to move gets to improve its position first, so it needs a smaller lead
+    if mine >= theirs + STATIC_MARGIN {
+        Some(player)
+    } else if theirs >= mine + 2 * STATIC_MARGIN {
+        Some(opponent)
+    } else {
+        None
+    }
+}
+
+// About the score of an open three, smaller differences are noise
+const STATIC_MARGIN: u32 = 100;
+
+fn softmax(values: impl Iterator<Item = f32> + Clone) -> Vec<f32> {
+    let max = values.clone().fold(f32::MIN, f32::max);
+    let exps: Vec<f32> = values.map(|v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|e| e / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_prior() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        let moves = board.gen_ordered_moves_all(2);
+        let priors = HeuristicPrior::default().priors(&board, 2, &moves);
+        assert_eq!(priors.len(), moves.len());
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        // Moves are ordered by score, so are their priors
+        assert!(priors.windows(2).all(|p| p[0] >= p[1]));
+        // Blocking the open three is by far the most likely move
+        assert!(priors[0] > 0.3);
+    }
+
+    #[test]
+    fn test_rollout_policies() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        let moves = board.gen_ordered_moves_all(2);
+        let best = moves[0].score;
+        let rng = &mut GameRng::new(7);
+        for _ in 0..20 {
+            let mv = HeuristicRollout.choose(&board, 2, &moves, rng).unwrap();
+            assert_eq!(mv.score, best);
+            let greedy = EpsilonGreedyRollout { epsilon: 0.0 };
+            assert_eq!(greedy.choose(&board, 2, &moves, rng).unwrap().score, best);
+            let mv = UniformRollout.choose(&board, 2, &moves, rng).unwrap();
+            assert!(moves.iter().any(|m| (m.x, m.y) == (mv.x, mv.y)));
+        }
+        assert!(UniformRollout.choose(&board, 2, &[], rng).is_none());
+        assert_eq!(TruncatedRollout { depth: 4 }.max_depth(), Some(4));
+        assert_eq!(HeuristicRollout.max_depth(), None);
+    }
+
+    #[test]
+    fn test_static_winner() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(8, 8, 2);
+        assert_eq!(static_winner(&mut board, 1), None);
+        // An open three against scattered stones
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        board.place(0, 0, 2);
+        assert_eq!(static_winner(&mut board, 1), Some(1));
+        assert_eq!(static_winner(&mut board, 2), Some(1));
+    }
+}
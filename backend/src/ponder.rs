@@ -0,0 +1,204 @@
+#![allow(dead_code)]
+// Pondering - keep searching while the opponent is thinking
+use super::algo::{create_engine, Engine, EngineOptions, UnknownEngine};
+use super::board::*;
+use super::search::{SearchControl, SearchLimits, SearchResult, StopToken};
+use std::thread::JoinHandle;
+
+// Pondering may run this many times the simulations or positions of a search,
+// it is stopped as soon as the opponent moves anyway
+const PONDER_BUDGET: u64 = 20;
+
+struct Pondering {
+    board: Board,
+    stop: StopToken,
+    handle: JoinHandle<Box<dyn Engine>>,
+}
+
+/* Plays one side of a game and thinks on the opponent's time.
+ * After our move `start` predicts the reply and searches the position after it
+ * in the background. On a ponder hit the next `best_move` keeps the tree or the
+ * transposition table of the engine, on a miss they are discarded with
+ * `Engine::new_game` and the search starts from scratch.
+ */
+pub struct Ponderer {
+    // None while the engine is pondering on its own thread
+    engine: Option<Box<dyn Engine>>,
+    limits: SearchLimits,
+    pondering: Option<Pondering>,
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl Ponderer {
+    /// Ponder with the registered engine `algo_type`
+    pub fn new(algo_type: &str, options: &EngineOptions) -> Result<Self, UnknownEngine> {
+        let mut engine = create_engine(algo_type, options)?;
+        engine.new_game();
+        Ok(Self::with_engine(engine, options.limits()))
+    }
+
+    /// Ponder with an engine created by the caller, searching with `limits`
+    pub fn with_engine(engine: Box<dyn Engine>, limits: SearchLimits) -> Self {
+        Self {
+            engine: Some(engine),
+            limits,
+            pondering: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.engine.as_ref().map_or("", |engine| engine.name())
+    }
+
+    /// Search `board`, the result of pondering on it is kept on a hit
+    pub fn best_move(&mut self, board: &Board) -> SearchResult {
+        match self.stop() {
+            Some(pondered) if pondered.to_string() == board.to_string() => self.hits += 1,
+            Some(_) => {
+                self.misses += 1;
+                self.engine_mut().new_game();
+            }
+            None => (),
+        }
+        let limits = self.limits;
+        let engine = self.engine_mut();
+        engine.set_control(SearchControl::default());
+        engine.set_position(board);
+        engine.search(&limits)
+    }
+
+    /// Start pondering on `board`, which must already contain our move.
+    /// Returns the predicted reply, or `None` if there is nothing to ponder on
+    pub fn start(&mut self, board: &Board) -> Option<Move> {
+        self.stop();
+        if board.any_winner().is_some() {
+            return None;
+        }
+        let reply = self.predicted_reply(board)?;
+        let mut pondered = board.clone();
+        pondered.place(reply.x, reply.y, board.next_player());
+        if pondered.any_winner().is_some() {
+            return None;
+        }
+
+        let mut engine = self.engine.take()?;
+        let stop = StopToken::new();
+        engine.set_control(SearchControl::new(stop.clone(), None));
+        let limits = SearchLimits {
+            nodes: self.limits.nodes.map(|n| n.saturating_mul(PONDER_BUDGET)),
+            time_ms: None,
+            ..self.limits
+        };
+        let position = pondered.clone();
+        let handle = std::thread::spawn(move || {
+            engine.set_position(&position);
+            engine.search(&limits);
+            engine
+        });
+        self.pondering = Some(Pondering {
+            board: pondered,
+            stop,
+            handle,
+        });
+        Some(reply)
+    }
+
+    /// Stop pondering, returns the pondered position
+    pub fn stop(&mut self) -> Option<Board> {
+        let pondering = self.pondering.take()?;
+        pondering.stop.stop();
+        let engine = pondering.handle.join().expect("ponder thread panicked");
+        self.engine = Some(engine);
+        Some(pondering.board)
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.is_some()
+    }
+
+    // The opponent's answer on `board` guessed by the engine, or the best
+    // candidate when it has no guess
+    fn predicted_reply(&self, board: &Board) -> Option<Move> {
+        let guess = self.engine.as_ref()?.predicted_reply();
+        match guess {
+            Some(mv) if board.get(mv.x as i32, mv.y as i32) == Some(0) => Some(mv),
+            _ => board
+                .clone()
+                .gen_ordered_moves_all(board.next_player())
+                .first()
+                .copied(),
+        }
+    }
+
+    fn engine_mut(&mut self) -> &mut Box<dyn Engine> {
+        self.engine.as_mut().expect("engine is pondering")
+    }
+}
+
+impl Drop for Ponderer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> EngineOptions {
+        EngineOptions {
+            threads: Some(1),
+            seed: Some(1),
+            depth: Some(3),
+            nodes: Some(100),
+            ..EngineOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_ponder_hit() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut ponderer = Ponderer::new("minimax", &options()).unwrap();
+        let mv = ponderer.best_move(&board).best_move;
+        board.place(mv.x, mv.y, 2);
+
+        let reply = ponderer.start(&board).unwrap();
+        assert!(ponderer.is_pondering());
+        board.place(reply.x, reply.y, 1);
+        let mv = ponderer.best_move(&board).best_move;
+        assert_eq!(board.get(mv.x as i32, mv.y as i32), Some(0));
+        assert_eq!(ponderer.hits, 1);
+        assert_eq!(ponderer.misses, 0);
+        assert!(!ponderer.is_pondering());
+    }
+
+    #[test]
+    fn test_ponder_miss() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        let mut ponderer = Ponderer::new("monte_carlo", &options()).unwrap();
+        let mv = ponderer.best_move(&board).best_move;
+        board.place(mv.x, mv.y, 2);
+
+        let reply = ponderer.start(&board).unwrap();
+        let (x, y) = if (reply.x, reply.y) == (0, 0) {
+            (0, 1)
+        } else {
+            (0, 0)
+        };
+        board.place(x, y, 1);
+        let mv = ponderer.best_move(&board).best_move;
+        assert_eq!(board.get(mv.x as i32, mv.y as i32), Some(0));
+        assert_eq!(ponderer.hits, 0);
+        assert_eq!(ponderer.misses, 1);
+    }
+
+    #[test]
+    fn test_ponder_unknown_engine() {
+        assert!(Ponderer::new("alphazero", &options()).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+#![allow(dead_code)]
+// Seedable randomness, so searches and self-play games can be replayed
+#[cfg(feature = "random")]
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+/// Random numbers of a search or a game. Generators created from the same
+/// seed return the same numbers. Without the `random` feature every choice
+/// falls on the first option.
+#[derive(Debug, Clone)]
+pub struct GameRng {
+    seed: u64,
+    // Boxed, the generator state is large compared to the searches holding it
+    #[cfg(feature = "random")]
+    rng: Box<StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            #[cfg(feature = "random")]
+            rng: Box::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// A generator with a fresh seed, for searches which do not need to be replayed
+    pub fn from_entropy() -> Self {
+        Self::new(random_seed())
+    }
+
+    /// The seed the generator was created from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "random")] {
+                self.rng.gen_range(0..n)
+            } else {
+                0
+            }
+        }
+    }
+
+    /// `true` with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "random")] {
+                self.rng.gen_bool(p.clamp(0.0, 1.0))
+            } else {
+                let _ = p;
+                false
+            }
+        }
+    }
+
+    /// Seed for another generator, e.g. of the next game or another search thread
+    pub fn next_seed(&mut self) -> u64 {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "random")] {
+                self.rng.next_u64()
+            } else {
+                self.seed = self.seed.wrapping_add(1);
+                self.seed
+            }
+        }
+    }
+
+    /// A new generator seeded from this one
+    pub fn fork(&mut self) -> GameRng {
+        GameRng::new(self.next_seed())
+    }
+}
+
+/// A seed which differs from run to run
+pub fn random_seed() -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "random")] {
+            rand::random()
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        let xs: Vec<usize> = (0..20).map(|_| a.below(1000)).collect();
+        let ys: Vec<usize> = (0..20).map(|_| b.below(1000)).collect();
+        assert_eq!(xs, ys);
+        assert_eq!(a.fork().below(1000), b.fork().below(1000));
+        assert_eq!(a.seed(), 42);
+        assert!(!a.chance(0.0));
+        assert!(a.chance(1.0));
+    }
+}
@@ -0,0 +1,527 @@
+#![allow(dead_code)]
+// JSON-RPC 2.0 engine mode - one request per line on stdin, one answer per line on stdout
+use super::algo::{create_engine, list_engines, Engine, EngineOptions};
+use super::board::*;
+use super::search::{SearchControl, SearchProgress, SearchResult, StopToken};
+use super::utils::MAX_BOARD_SIZE;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Requests which are valid but cannot be done now, e.g. a move on a taken cell
+const REFUSED: i64 = -32000;
+
+// Candidates listed by `analyze`
+const ANALYZE_CANDIDATES: usize = 5;
+
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: Option<String>,
+    // Requests without an id are notifications and get no answer
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NewGameParams {
+    engine: Option<String>,
+    width: Option<usize>,
+    height: Option<usize>,
+    exact_five: bool,
+    options: Option<EngineOptions>,
+}
+
+#[derive(Deserialize)]
+struct PlayParams {
+    row: usize,
+    col: usize,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct UndoParams {
+    count: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SearchParams {
+    // Limits of this search, the options of the game are used for unset ones
+    options: Option<EngineOptions>,
+    // `best_move` plays the move it found
+    play: bool,
+}
+
+enum Event {
+    Line(String),
+    Finished(Box<dyn Engine>, SearchResult),
+    Closed,
+}
+
+struct RunningSearch {
+    id: Option<Value>,
+    method: String,
+    play: bool,
+    stop: StopToken,
+}
+
+/* The state kept between requests: the engine, the position and its moves.
+ * A search runs on its own thread, so `stop` can be answered meanwhile. Other
+ * requests arriving during a search wait and are handled in order after it.
+ */
+pub struct Session {
+    algo: String,
+    options: EngineOptions,
+    // Taken by the running search
+    engine: Option<Box<dyn Engine>>,
+    board: Board,
+    history: Vec<(usize, usize)>,
+    search: Option<RunningSearch>,
+    queue: VecDeque<String>,
+    output: Output,
+    events: Sender<Event>,
+}
+
+impl Session {
+    fn new(
+        algo: &str,
+        options: &EngineOptions,
+        board: Board,
+        output: Output,
+        events: Sender<Event>,
+    ) -> Self {
+        Self {
+            algo: algo.to_string(),
+            options: options.clone(),
+            engine: create_engine(algo, options).ok(),
+            board,
+            history: vec![],
+            search: None,
+            queue: VecDeque::new(),
+            output,
+            events,
+        }
+    }
+
+    fn send(&self, message: &Value) {
+        let mut output = self.output.lock().unwrap();
+        let _ = writeln!(output, "{}", message);
+        let _ = output.flush();
+    }
+
+    fn reply(&self, id: Option<Value>, result: Result<Value, (i64, String)>) {
+        let id = match id {
+            Some(id) => id,
+            None => return,
+        };
+        let message = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        };
+        self.send(&message);
+    }
+
+    fn handle_line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.search.is_some() {
+            match serde_json::from_str::<Request>(line) {
+                Ok(request) if request.method == "stop" => {
+                    let result = self.stop();
+                    self.reply(request.id, result);
+                }
+                _ => self.queue.push_back(line.to_string()),
+            }
+            return;
+        }
+        let request: Request = match serde_json::from_str::<Value>(line) {
+            Err(e) => return self.reply(Some(Value::Null), Err((PARSE_ERROR, e.to_string()))),
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => {
+                    return self.reply(Some(Value::Null), Err((INVALID_REQUEST, e.to_string())))
+                }
+            },
+        };
+        if request.jsonrpc.as_deref() != Some("2.0") {
+            let message = "jsonrpc must be \"2.0\"".to_string();
+            return self.reply(request.id, Err((INVALID_REQUEST, message)));
+        }
+        let params = request.params;
+        let result = match request.method.as_str() {
+            "list_engines" => Ok(json!(list_engines())),
+            "new_game" => parse(params).and_then(|p| self.new_game(p)),
+            "play" => parse_required(params).and_then(|p| self.play(p)),
+            "undo" => parse(params).and_then(|p| self.undo(p)),
+            "winner" => Ok(self.state()),
+            "stop" => self.stop(),
+            "best_move" | "analyze" => match parse(params) {
+                Ok(p) => match self.start_search(request.id.clone(), &request.method, p) {
+                    // Answered when the search is finished
+                    Ok(()) => return,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            },
+            method => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+        self.reply(request.id, result);
+    }
+
+    fn new_game(&mut self, params: NewGameParams) -> Result<Value, (i64, String)> {
+        let algo = params.engine.unwrap_or_else(|| self.algo.clone());
+        let options = params.options.unwrap_or_else(|| self.options.clone());
+        let engine = create_engine(&algo, &options).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+        let width = params.width.unwrap_or(self.board.width);
+        let height = params.height.unwrap_or(self.board.height);
+        if width < 5 || height < 5 {
+            return Err((
+                INVALID_PARAMS,
+                format!("board {}x{} is too small", width, height),
+            ));
+        }
+        if width > MAX_BOARD_SIZE || height > MAX_BOARD_SIZE {
+            return Err((
+                INVALID_PARAMS,
+                format!("board {}x{} is too large", width, height),
+            ));
+        }
+        self.board = Board::new("0".repeat(width * height), width, height);
+        self.board.exact_five = params.exact_five;
+        self.history.clear();
+        self.algo = algo;
+        self.options = options;
+        self.engine = Some(engine);
+        Ok(self.state())
+    }
+
+    fn play(&mut self, params: PlayParams) -> Result<Value, (i64, String)> {
+        self.place(params.row, params.col)?;
+        Ok(self.state())
+    }
+
+    fn place(&mut self, row: usize, col: usize) -> Result<(), (i64, String)> {
+        if self.board.any_winner().is_some() {
+            return Err((REFUSED, "the game is over".to_string()));
+        }
+        if self.board.get(row as i32, col as i32) != Some(0) {
+            return Err((REFUSED, format!("({}, {}) is not an empty cell", row, col)));
+        }
+        let player = self.board.next_player();
+        self.board.place(row, col, player);
+        self.history.push((row, col));
+        Ok(())
+    }
+
+    fn undo(&mut self, params: UndoParams) -> Result<Value, (i64, String)> {
+        let count = params.count.unwrap_or(1);
+        if count > self.history.len() {
+            let message = format!("only {} moves to take back", self.history.len());
+            return Err((REFUSED, message));
+        }
+        for _ in 0..count {
+            let (row, col) = self.history.pop().unwrap();
+            self.board.place(row, col, 0);
+        }
+        Ok(self.state())
+    }
+
+    fn stop(&mut self) -> Result<Value, (i64, String)> {
+        let running = self.search.as_ref().map(|s| s.stop.stop()).is_some();
+        Ok(json!({ "stopped": running }))
+    }
+
+    // The position after the last request
+    fn state(&self) -> Value {
+        let moves: Vec<Value> = self
+            .history
+            .iter()
+            .map(|(row, col)| json!({"row": row, "col": col}))
+            .collect();
+        json!({
+            "engine": self.algo,
+            "width": self.board.width,
+            "height": self.board.height,
+            "board": self.board.to_string(),
+            "moves": moves,
+            "next_player": self.board.next_player(),
+            "winner": self.board.any_winner(),
+        })
+    }
+
+    fn start_search(
+        &mut self,
+        id: Option<Value>,
+        method: &str,
+        params: SearchParams,
+    ) -> Result<(), (i64, String)> {
+        if self.board.any_winner().is_some() || self.board.empty_cells_count() == 0 {
+            return Err((REFUSED, "the game is over".to_string()));
+        }
+        let mut engine = self
+            .engine
+            .take()
+            .ok_or((REFUSED, format!("unknown engine {}", self.algo)))?;
+        let limits = match params.options {
+            Some(options) => options.or(&self.options).limits(),
+            None => self.options.limits(),
+        };
+        let stop = StopToken::new();
+        let output = self.output.clone();
+        let progress_id = id.clone();
+        let progress = Arc::new(move |p: &SearchProgress| {
+            let message = json!({
+                "jsonrpc": "2.0",
+                "method": "progress",
+                "params": {"id": progress_id, "progress": p},
+            });
+            let mut output = output.lock().unwrap();
+            let _ = writeln!(output, "{}", message);
+            let _ = output.flush();
+        });
+        engine.set_control(SearchControl::new(stop.clone(), Some(progress)));
+        engine.set_position(&self.board);
+        self.search = Some(RunningSearch {
+            id,
+            method: method.to_string(),
+            play: params.play,
+            stop,
+        });
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let result = engine.search(&limits);
+            let _ = events.send(Event::Finished(engine, result));
+        });
+        Ok(())
+    }
+
+    fn finish_search(&mut self, engine: Box<dyn Engine>, res: SearchResult) {
+        self.engine = Some(engine);
+        let search = self.search.take().unwrap();
+        if !res.has_move() {
+            let message = format!("no move from {}", self.algo);
+            return self.reply(search.id, Err((REFUSED, message)));
+        }
+        let (row, col) = (res.best_move.x, res.best_move.y);
+        let mut result = json!({
+            "row": row,
+            "col": col,
+            "score": res.best_move.score,
+            "player": self.board.next_player(),
+            "stats": res.stats,
+            "limits": res.limits,
+        });
+        if search.method == "analyze" {
+            let player = self.board.next_player();
+            let mut board = self.board.clone();
+            let candidates: Vec<Value> = board
+                .gen_ordered_moves_all(player)
+                .iter()
+                .take(ANALYZE_CANDIDATES)
+                .map(|m| json!({"row": m.x, "col": m.y, "score": m.score}))
+                .collect();
+            result["candidates"] = json!(candidates);
+        }
+        if search.play {
+            if let Err(e) = self.place(row, col) {
+                return self.reply(search.id, Err(e));
+            }
+            result["state"] = self.state();
+        }
+        self.reply(search.id, Ok(result));
+    }
+}
+
+// Params which may be left out
+fn parse<T: DeserializeOwned + Default>(params: Value) -> Result<T, (i64, String)> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    parse_required(params)
+}
+
+fn parse_required<T: DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+/// Answer the requests read from `input` until it is closed and every request is
+/// answered. `algo`, `options` and `board` set up the first game.
+pub fn run<R: BufRead + Send + 'static>(
+    input: R,
+    output: Output,
+    algo: &str,
+    options: &EngineOptions,
+    board: Board,
+) {
+    let (events, receiver) = channel();
+    let mut session = Session::new(algo, options, board, output, events.clone());
+    thread::spawn(move || {
+        for line in input.lines().map_while(Result::ok) {
+            if events.send(Event::Line(line)).is_err() {
+                return;
+            }
+        }
+        let _ = events.send(Event::Closed);
+    });
+    let mut closed = false;
+    while let Ok(event) = receiver.recv() {
+        match event {
+            Event::Line(line) => session.handle_line(&line),
+            Event::Finished(engine, res) => {
+                session.finish_search(engine, res);
+                while session.search.is_none() {
+                    match session.queue.pop_front() {
+                        Some(line) => session.handle_line(&line),
+                        None => break,
+                    }
+                }
+            }
+            Event::Closed => closed = true,
+        }
+        if closed && session.search.is_none() && session.queue.is_empty() {
+            break;
+        }
+    }
+}
+
+/// `run` on stdin and stdout
+pub fn run_stdio(algo: &str, options: &EngineOptions, board: Board) {
+    let output: Output = Arc::new(Mutex::new(std::io::stdout()));
+    run(
+        std::io::BufReader::new(std::io::stdin()),
+        output,
+        algo,
+        options,
+        board,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn session(requests: &[&str]) -> Vec<Value> {
+        let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let output: Output = buffer.clone();
+        let input = Cursor::new(requests.join("\n").into_bytes());
+        let options = EngineOptions {
+            threads: Some(1),
+            ..EngineOptions::default()
+        };
+        run(input, output, "minimax", &options, Board::new_default());
+        let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_rpc_session() {
+        let answers = session(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"new_game","params":{"engine":"one_ply"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"play","params":{"row":7,"col":7}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"play","params":{"row":7,"col":7}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"best_move","params":{"play":true}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"undo","params":{"count":2}}"#,
+            r#"{"jsonrpc":"2.0","method":"play","params":{"row":0,"col":0}}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"winner"}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"new_game","params":{"engine":"minimax","options":{"depth":2}}}"#,
+            r#"{"jsonrpc":"2.0","id":8,"method":"analyze"}"#,
+            r#"{"jsonrpc":"2.0","id":9,"method":"list_engines"}"#,
+            r#"{"jsonrpc":"2.0","id":10,"method":"resign"}"#,
+            r#"{"jsonrpc":"2.0","id":11,"method":"undo","params":{"count":3}}"#,
+            r#"{"id":12,"method":"winner"}"#,
+            r#"{"jsonrpc":"2.0","id":13,"method":"new_game","params":{"width":1000000,"height":1000000}}"#,
+            "not json",
+        ]);
+        let by_id = |id: i64| -> &Value { answers.iter().find(|a| a["id"] == json!(id)).unwrap() };
+
+        assert_eq!(by_id(1)["result"]["engine"], "one_ply");
+        assert_eq!(by_id(2)["result"]["next_player"], 2);
+        assert_eq!(by_id(3)["error"]["code"], REFUSED);
+        let best = &by_id(4)["result"];
+        assert_eq!(best["player"], 2);
+        assert_eq!(best["state"]["moves"].as_array().unwrap().len(), 2);
+        assert_eq!(by_id(5)["result"]["moves"], json!([]));
+        // The notification was played without an answer
+        assert_eq!(by_id(6)["result"]["moves"], json!([{"row": 0, "col": 0}]));
+        assert_eq!(by_id(6)["result"]["winner"], Value::Null);
+
+        // analyze reports the progress of each depth before its answer
+        let analyze = answers.iter().position(|a| a["id"] == json!(8)).unwrap();
+        let progress: Vec<&Value> = answers[..analyze]
+            .iter()
+            .filter(|a| a["method"] == "progress" && a["params"]["id"] == json!(8))
+            .collect();
+        assert_eq!(progress.len(), 2);
+        let result = &answers[analyze]["result"];
+        assert_eq!(result["stats"]["depth"], 2);
+        assert!(!result["candidates"].as_array().unwrap().is_empty());
+
+        assert!(by_id(9)["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e["name"] == "minimax"));
+        assert_eq!(by_id(10)["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(by_id(11)["error"]["code"], REFUSED);
+        assert_eq!(by_id(12)["error"]["code"], INVALID_REQUEST);
+        assert_eq!(by_id(13)["error"]["code"], INVALID_PARAMS);
+        assert_eq!(answers.last().unwrap()["error"]["code"], PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_json_rpc_stop() {
+        // Without stop the depth 12 search would not finish
+        let answers = session(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"play","params":{"row":7,"col":7}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"best_move","params":{"options":{"depth":12}}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"winner"}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"stop"}"#,
+        ]);
+        let ids: Vec<&Value> = answers
+            .iter()
+            .filter(|a| a.get("id").is_some())
+            .map(|a| &a["id"])
+            .collect();
+        assert_eq!(ids, vec![&json!(1), &json!(4), &json!(2), &json!(3)]);
+        assert_eq!(
+            answers.iter().find(|a| a["id"] == json!(4)).unwrap()["result"]["stopped"],
+            true
+        );
+        let best = &answers.iter().find(|a| a["id"] == json!(2)).unwrap()["result"];
+        assert!(best["stats"]["depth"].as_i64().unwrap() < 12);
+    }
+
+    #[test]
+    fn test_json_rpc_search_options() {
+        // The depth of the game is kept when the search only sets the nodes
+        let answers = session(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"new_game","params":{"options":{"depth":2}}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"best_move","params":{"options":{"nodes":100000}}}"#,
+        ]);
+        let limits = &answers.iter().find(|a| a["id"] == json!(2)).unwrap()["result"]["limits"];
+        assert_eq!(limits["depth"], 2);
+        assert_eq!(limits["nodes"], 100000);
+    }
+}
@@ -0,0 +1,217 @@
+#![allow(dead_code)]
+use super::board::Move;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag which asks a running search to return as soon as possible
+#[derive(Debug, Clone, Default)]
+pub struct StopToken(Arc<AtomicBool>);
+
+impl StopToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Snapshot of a running search, handed to the progress callback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchProgress {
+    pub depth: i32,
+    pub nodes: u64,
+    pub best_move: Option<(usize, usize)>,
+    pub score: i32,
+}
+
+/// Budget of a search, it stops at whichever limit is reached first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchLimits {
+    pub time_ms: Option<u64>,
+    /// Simulations for MonteCarlo, positions searched for MiniMax
+    pub nodes: Option<u64>,
+    /// Deepest iteration of MiniMax, MonteCarlo does not look at it
+    pub depth: Option<i32>,
+}
+
+impl SearchLimits {
+    pub fn time(time_ms: u64) -> Self {
+        Self {
+            time_ms: Some(time_ms),
+            ..Self::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..Self::default()
+        }
+    }
+
+    pub fn depth(depth: i32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+
+    /// Neither a time nor a node budget, the engines fall back to their defaults
+    pub fn is_unlimited(&self) -> bool {
+        self.time_ms.is_none() && self.nodes.is_none()
+    }
+}
+
+/// What a finished search actually did
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchStats {
+    /// Deepest finished MiniMax iteration, deepest MonteCarlo simulation
+    pub depth: i32,
+    /// Positions searched by MiniMax, size of the MonteCarlo trees
+    pub nodes: u64,
+    /// Positions scored by the move generator or the static evaluation
+    pub evaluations: u64,
+    pub simulations: u64,
+    /// Positions answered by the transposition table
+    pub tt_hits: u64,
+    pub elapsed_ms: u64,
+    /// Threads the search ran on, 0 when nothing was searched here, e.g. a book move
+    pub threads: usize,
+}
+
+impl SearchStats {
+    pub fn nodes_per_second(&self) -> u64 {
+        self.nodes * 1000 / self.elapsed_ms.max(1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Move,
+    pub stats: SearchStats,
+    /// Limits the search ran with, the defaults of the engine filled in
+    pub limits: SearchLimits,
+}
+
+// Row and column of the move of `SearchResult::no_move`, outside of every board
+const NO_CELL: usize = usize::MAX;
+
+impl SearchResult {
+    /// Result of a search which found nothing to play, e.g. on a full board or when
+    /// an external brain failed. The side to move loses a game it cannot move in.
+    pub fn no_move(stats: SearchStats, limits: SearchLimits) -> Self {
+        Self {
+            best_move: Move::new(NO_CELL, NO_CELL, 0, 0),
+            stats,
+            limits,
+        }
+    }
+
+    /// Whether `best_move` is a cell to play, false for `no_move`
+    pub fn has_move(&self) -> bool {
+        self.best_move.x != NO_CELL
+    }
+}
+
+/// Wall clock timer, `std::time::Instant` is not available in the browser
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                Self { start: js_sys::Date::now() }
+            } else {
+                Self { start: std::time::Instant::now() }
+            }
+        }
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                (js_sys::Date::now() - self.start).max(0.0) as u64
+            } else {
+                self.start.elapsed().as_millis() as u64
+            }
+        }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&SearchProgress) + Send + Sync>;
+
+/// Everything a caller can use to steer a search from the outside
+#[derive(Clone, Default)]
+pub struct SearchControl {
+    pub stop: StopToken,
+    pub progress: Option<ProgressCallback>,
+}
+
+impl SearchControl {
+    pub fn new(stop: StopToken, progress: Option<ProgressCallback>) -> Self {
+        Self { stop, progress }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.is_stopped()
+    }
+
+    pub fn report(&self, progress: SearchProgress) {
+        if let Some(callback) = &self.progress {
+            callback(&progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_stop_token_shared() {
+        let token = StopToken::new();
+        let other = token.clone();
+        assert!(!token.is_stopped());
+        other.stop();
+        assert!(token.is_stopped());
+        token.reset();
+        assert!(!other.is_stopped());
+    }
+
+    #[test]
+    fn test_control_report() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let sink = seen.clone();
+        let control = SearchControl::new(
+            StopToken::new(),
+            Some(Arc::new(move |p: &SearchProgress| {
+                sink.lock().unwrap().push(p.depth)
+            })),
+        );
+        control.report(SearchProgress {
+            depth: 3,
+            nodes: 10,
+            best_move: Some((7, 7)),
+            score: 0,
+        });
+        assert_eq!(*seen.lock().unwrap(), vec![3]);
+    }
+}
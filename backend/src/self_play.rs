@@ -1,16 +1,18 @@
 // 自我对弈模块 - 用于生成训练数据
-use super::algo::gomoku_solve;
+use super::algo::{create_engine_with_external, Engine, EngineOptions};
 use super::board::Board;
+use super::book::{self, OpeningBook};
 use super::game_record::{GameRecord, GameState};
+use super::rng::{random_seed, GameRng};
 use crate::utils::BOARD_HEIGHT;
 
-#[cfg(feature = "random")]
-use rand::Rng;
-
 pub struct SelfPlay {
     max_steps: usize,
     verbose: bool,
     random_opening_steps: usize,
+    opening_book: Option<OpeningBook>,
+    seed: Option<u64>,
+    levels: (Option<u8>, Option<u8>),
 }
 
 impl SelfPlay {
@@ -19,6 +21,9 @@ impl SelfPlay {
             max_steps,
             verbose,
             random_opening_steps: 0,
+            opening_book: None,
+            seed: None,
+            levels: (None, None),
         }
     }
 
@@ -28,12 +33,35 @@ impl SelfPlay {
             max_steps,
             verbose,
             random_opening_steps: opening_steps.min(3), // 最多3步
+            opening_book: None,
+            seed: None,
+            levels: (None, None),
         }
     }
 
+    /// 固定随机种子：第 i 局（或第 i 个开局）使用种子 seed + i，
+    /// 同样的种子和设置会下出完全一样的对局
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// algo1 和 algo2 的难度等级 (1-10)，None 表示全力，见 difficulty::Difficulty
+    pub fn set_levels(&mut self, level1: Option<u8>, level2: Option<u8>) {
+        self.levels = (level1, level2);
+    }
+
+    /// 从开局库中随机选取开局（按对局数加权），最多 opening_steps 步
+    pub fn set_opening_book(&mut self, book: OpeningBook, opening_steps: usize) {
+        self.random_opening_steps = opening_steps;
+        self.opening_book = Some(book);
+    }
+
     /// 随机生成开局棋形（不指定玩家，只是位置）
     /// 返回格子位置列表，调用者决定谁下哪个子
-    fn generate_random_opening_positions(&self) -> Vec<(usize, usize)> {
+    fn generate_random_opening_positions(&self, rng: &mut GameRng) -> Vec<(usize, usize)> {
+        if let Some(book) = &self.opening_book {
+            return book.random_line(self.random_opening_steps, rng);
+        }
         let mut positions = Vec::new();
 
         cfg_if::cfg_if! {
@@ -42,13 +70,12 @@ impl SelfPlay {
                     return positions;
                 }
 
-                let mut rng = rand::thread_rng();
                 let center: usize = BOARD_HEIGHT / 2; // center based on board height
 
                 // 第一步：在中心区域随机选择
                 let offset: usize = 2; // 中心 ±2 范围
-                let x1 = center.saturating_sub(offset) + rng.gen_range(0..=offset * 2);
-                let y1 = center.saturating_sub(offset) + rng.gen_range(0..=offset * 2);
+                let x1 = center.saturating_sub(offset) + rng.below(offset * 2 + 1);
+                let y1 = center.saturating_sub(offset) + rng.below(offset * 2 + 1);
 
                 positions.push((x1, y1));
 
@@ -56,8 +83,8 @@ impl SelfPlay {
                     // 第二步：在第一步附近
                     let nearby_range = 2;
                     for _ in 0..10 { // 最多尝试10次
-                        let x2 = x1.saturating_sub(nearby_range) + rng.gen_range(0..=nearby_range * 2);
-                        let y2 = y1.saturating_sub(nearby_range) + rng.gen_range(0..=nearby_range * 2);
+                        let x2 = x1.saturating_sub(nearby_range) + rng.below(nearby_range * 2 + 1);
+                        let y2 = y1.saturating_sub(nearby_range) + rng.below(nearby_range * 2 + 1);
 
                         // 确保不重复且在棋盘内
                         if x2 < 15 && y2 < 15 && (x2, y2) != (x1, y1) {
@@ -73,8 +100,8 @@ impl SelfPlay {
                     let nearby_range = 2;
 
                     for _ in 0..10 { // 最多尝试10次
-                        let x3 = last_x.saturating_sub(nearby_range) + rng.gen_range(0..=nearby_range * 2);
-                        let y3 = last_y.saturating_sub(nearby_range) + rng.gen_range(0..=nearby_range * 2);
+                        let x3 = last_x.saturating_sub(nearby_range) + rng.below(nearby_range * 2 + 1);
+                        let y3 = last_y.saturating_sub(nearby_range) + rng.below(nearby_range * 2 + 1);
 
                         if x3 < 15 && y3 < 15 && !positions.contains(&(x3, y3)) {
                             positions.push((x3, y3));
@@ -98,20 +125,89 @@ impl SelfPlay {
         opening_positions: Option<Vec<(usize, usize)>>,
         first_player_is_algo1: bool,
     ) -> GameRecord {
+        let seed = self.seed.unwrap_or_else(random_seed);
+        self.play_game_seeded(algo1, algo2, opening_positions, first_player_is_algo1, seed)
+    }
+
+    /// 重新下一遍有种子的对局，设置相同时得到完全一样的对局。
+    /// 开局必须是由种子生成的（没有传入 opening_positions）。
+    /// 多线程搜索的结果和线程的调度有关，所以只重下单线程的对局，
+    /// 其他的返回 None（没有记录线程数的旧对局当作单线程）
+    pub fn replay(&self, record: &GameRecord) -> Option<GameRecord> {
+        let seed = record.seed?;
+        if record.threads.unwrap_or(1) != 1 {
+            return None;
+        }
+        let options = |level| EngineOptions {
+            level,
+            threads: Some(1),
+            ..EngineOptions::default()
+        };
+        Some(self.play_game_leveled(
+            &record.algo_player1,
+            &record.algo_player2,
+            None,
+            true,
+            seed,
+            (options(record.level_player1), options(record.level_player2)),
+        ))
+    }
+
+    /// 同 play_game_with_opening，随机开局和搜索用的随机数都来自 seed，
+    /// 种子记录在 GameRecord 里
+    pub fn play_game_seeded(
+        &self,
+        algo1: &str,
+        algo2: &str,
+        opening_positions: Option<Vec<(usize, usize)>>,
+        first_player_is_algo1: bool,
+        seed: u64,
+    ) -> GameRecord {
+        let threads = EngineOptions::default().threads();
+        let options = |level| EngineOptions {
+            level,
+            threads: Some(threads),
+            ..EngineOptions::default()
+        };
+        self.play_game_leveled(
+            algo1,
+            algo2,
+            opening_positions,
+            first_player_is_algo1,
+            seed,
+            (options(self.levels.0), options(self.levels.1)),
+        )
+    }
+
+    // options 是 algo1 和 algo2 引擎的难度等级和线程数，种子由 seed 生成
+    fn play_game_leveled(
+        &self,
+        algo1: &str,
+        algo2: &str,
+        opening_positions: Option<Vec<(usize, usize)>>,
+        first_player_is_algo1: bool,
+        seed: u64,
+        options: (EngineOptions, EngineOptions),
+    ) -> GameRecord {
+        let mut rng = GameRng::new(seed);
         let mut board = Board::new_default();
 
         // 决定谁是 Player 1（先手）
-        let (player1_algo, player2_algo) = if first_player_is_algo1 {
-            (algo1, algo2)
-        } else {
-            (algo2, algo1)
-        };
+        let (player1_algo, player2_algo, player1_options, player2_options) =
+            if first_player_is_algo1 {
+                (algo1, algo2, options.0, options.1)
+            } else {
+                (algo2, algo1, options.1, options.0)
+            };
 
         let mut record = GameRecord::new(player1_algo.to_string(), player2_algo.to_string());
+        record.set_seed(seed);
+        record.set_levels(player1_options.level, player2_options.level);
+        record.set_threads(player1_options.threads().max(player2_options.threads()));
 
         // 使用提供的开局，或生成新的
         let opening_positions =
-            opening_positions.unwrap_or_else(|| self.generate_random_opening_positions());
+            opening_positions.unwrap_or_else(|| self.generate_random_opening_positions(&mut rng));
 
         if self.verbose && !opening_positions.is_empty() {
             println!("🎲 Random opening positions: {:?}", opening_positions);
@@ -149,6 +245,25 @@ impl SelfPlay {
             }
         }
 
+        // 每方一个引擎，整局复用：置换表、搜索树和外部程序都留到下一步
+        let mut engines: Vec<Box<dyn Engine>> = [
+            (player1_algo, player1_options),
+            (player2_algo, player2_options),
+        ]
+        .iter()
+        .map(|(algo, options)| {
+            let options = EngineOptions {
+                seed: Some(rng.next_seed()),
+                ..options.clone()
+            };
+            let mut engine =
+                create_engine_with_external(algo, &options).unwrap_or_else(|e| panic!("{}", e));
+            engine.new_game();
+            engine
+        })
+        .collect();
+        let limits = EngineOptions::default().limits();
+
         // 确定开局后的当前玩家
         let mut current_player = if opening_positions.len() % 2 == 0 {
             1u8
@@ -163,17 +278,31 @@ impl SelfPlay {
                 println!("Player: {}", current_player);
             }
 
-            // 选择算法（根据当前是 Player 1 还是 Player 2）
-            let algo = if current_player == 1 {
-                player1_algo
-            } else {
-                player2_algo
-            };
+            if board.empty_cells_count() == 0 {
+                // 棋盘下满，平局
+                break;
+            }
 
-            // 获取最佳落子
-            let board_str = board.to_string();
-            // 获取最佳落子
-            let best_move = gomoku_solve(&board_str, algo, board.width, board.height);
+            // 选择引擎（根据当前是 Player 1 还是 Player 2），开局库里有的局面不用搜索
+            let engine = &mut engines[current_player as usize - 1];
+            let best_move = match book::global_lookup(&board) {
+                Some(mv) => mv,
+                None => {
+                    engine.set_position(&board);
+                    let res = engine.search(&limits);
+                    let mv = res.best_move;
+                    if !res.has_move() || board.get(mv.x as i32, mv.y as i32) != Some(0) {
+                        // 走不出合法的棋（例如外部程序出错或超时）就判负
+                        let winner = if current_player == 1 { 2 } else { 1 };
+                        record.set_winner(Some(winner));
+                        if self.verbose {
+                            println!("{} failed to move, player {} wins", engine.name(), winner);
+                        }
+                        break;
+                    }
+                    mv
+                }
+            };
 
             if self.verbose {
                 println!(
@@ -258,43 +387,33 @@ impl SelfPlay {
         println!("   🚀 Using parallel execution");
 
         let counter = Arc::new(AtomicUsize::new(0));
+        let seed = self.seed.unwrap_or_else(random_seed);
 
         if self.random_opening_steps > 0 {
             // 策略：生成 num_games / 2 个开局棋形，每个棋形玩两局（双方各先手一次）
-            let num_openings = (num_games + 1) / 2;
-
-            // 预生成所有开局
-            let openings: Vec<Vec<(usize, usize)>> = (0..num_openings)
-                .map(|_| self.generate_random_opening_positions())
-                .collect();
-
-            // 构建游戏任务列表：(opening_idx, opening_positions, first_player_is_algo1)
+            // 同一个开局的两局使用同一个种子，开局由种子生成
             let mut tasks = Vec::new();
-            for (opening_idx, opening_positions) in openings.iter().enumerate() {
+            for opening_idx in 0..num_games.div_ceil(2) {
+                let opening_seed = seed.wrapping_add(opening_idx as u64);
                 if tasks.len() < num_games {
-                    tasks.push((opening_idx, opening_positions.clone(), true));
+                    tasks.push((opening_seed, true));
                 }
                 if tasks.len() < num_games {
-                    tasks.push((opening_idx, opening_positions.clone(), false));
+                    tasks.push((opening_seed, false));
                 }
             }
 
             // 并行执行游戏
             let records: Vec<GameRecord> = tasks
                 .par_iter()
-                .map(|(_opening_idx, opening_positions, first_player_is_algo1)| {
+                .map(|(opening_seed, first_player_is_algo1)| {
                     let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
                     if !self.verbose {
                         print!("\rProgress: {}/{}", count, num_games);
                         std::io::Write::flush(&mut std::io::stdout()).ok();
                     }
 
-                    self.play_game_with_opening(
-                        algo1,
-                        algo2,
-                        Some(opening_positions.clone()),
-                        *first_player_is_algo1,
-                    )
+                    self.play_game_seeded(algo1, algo2, None, *first_player_is_algo1, *opening_seed)
                 })
                 .collect();
 
@@ -316,7 +435,8 @@ impl SelfPlay {
                     }
 
                     let first_player_is_algo1 = i % 2 == 0;
-                    self.play_game_with_opening(algo1, algo2, None, first_player_is_algo1)
+                    let game_seed = seed.wrapping_add(i as u64);
+                    self.play_game_seeded(algo1, algo2, None, first_player_is_algo1, game_seed)
                 })
                 .collect();
 
@@ -334,6 +454,7 @@ impl SelfPlay {
 pub struct Tournament {
     algorithms: Vec<String>,
     games_per_pair: usize,
+    seed: Option<u64>,
 }
 
 impl Tournament {
@@ -341,12 +462,19 @@ impl Tournament {
         Self {
             algorithms,
             games_per_pair,
+            seed: None,
         }
     }
 
+    /// 固定随机种子，每对算法的种子都由它生成
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
     pub fn run(&self) -> Vec<GameRecord> {
         let mut all_records = Vec::new();
-        let self_play = SelfPlay::new(300, false);
+        let mut self_play = SelfPlay::new(300, false);
+        let mut rng = GameRng::new(self.seed.unwrap_or_else(random_seed));
 
         println!("\n🏆 Tournament Mode");
         println!("Algorithms: {:?}", self.algorithms);
@@ -362,6 +490,7 @@ impl Tournament {
                 let algo2 = &self.algorithms[j];
 
                 println!("\n📊 {} vs {}", algo1, algo2);
+                self_play.set_seed(rng.next_seed());
                 let records = self_play.play_multiple_games(self.games_per_pair, algo1, algo2);
                 all_records.extend(records);
             }
@@ -422,4 +551,37 @@ mod tests {
         let record = self_play.play_game_with_opening("minimax", "minimax", None, true);
         assert!(record.total_steps > 0);
     }
+
+    #[test]
+    fn test_self_play_replay() {
+        let mut self_play = SelfPlay::new_with_random_opening(12, false, 3);
+        self_play.set_seed(5);
+        let moves = |r: &GameRecord| -> Vec<(usize, usize)> {
+            r.states.iter().map(|s| (s.move_x, s.move_y)).collect()
+        };
+        let record = self_play.play_game_with_opening("monte_carlo", "minimax", None, true);
+        assert_eq!(record.seed, Some(5));
+        let replayed = self_play.replay(&record).unwrap();
+        assert_eq!(moves(&replayed), moves(&record));
+        assert_eq!(replayed.winner, record.winner);
+
+        // 难度等级跟着算法走，重下时也一样
+        self_play.set_levels(Some(3), None);
+        let record = self_play.play_game_with_opening("monte_carlo", "minimax", None, false);
+        assert_eq!(record.algo_player2, "monte_carlo");
+        assert_eq!(
+            (record.level_player1, record.level_player2),
+            (None, Some(3))
+        );
+        let replayed = self_play.replay(&record).unwrap();
+        assert_eq!(moves(&replayed), moves(&record));
+
+        // 多线程的对局不重下
+        assert_eq!(record.threads, Some(EngineOptions::default().threads()));
+        let threaded = GameRecord {
+            threads: Some(4),
+            ..record
+        };
+        assert!(self_play.replay(&threaded).is_none());
+    }
 }
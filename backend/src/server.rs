@@ -1,12 +1,17 @@
+use super::algo::{self, EngineOptions};
 use super::control;
+use super::search::{SearchControl, SearchProgress, StopToken};
 use crate::utils::{BOARD_HEIGHT, BOARD_WIDTH};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 use warp::{
     fs::dir,
     http::{Response, StatusCode},
-    Filter,
+    Filter, Rejection, Reply,
 };
 
 #[derive(Deserialize, Serialize)]
@@ -15,10 +20,136 @@ struct ReqObject {
     algo_type: String,
     width: Option<usize>,
     height: Option<usize>,
+    search_id: Option<String>,
+    // Engine options, see `algo::EngineOptions`. Spelled out because the
+    // query parser cannot read numbers into a flattened struct
+    threads: Option<usize>,
+    seed: Option<u64>,
+    depth: Option<i32>,
+    nodes: Option<u64>,
+    time_ms: Option<u64>,
+    level: Option<u8>,
+}
+
+impl ReqObject {
+    // The options of the query, capped since anyone can send them
+    fn options(&self) -> EngineOptions {
+        EngineOptions {
+            threads: self.threads,
+            seed: self.seed,
+            depth: self.depth,
+            nodes: self.nodes,
+            time_ms: self.time_ms,
+            level: self.level,
+        }
+        .capped()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct SearchReq {
+    search_id: String,
+}
+
+#[derive(Clone)]
+struct ActiveSearch {
+    stop: StopToken,
+    progress: Arc<Mutex<Option<SearchProgress>>>,
+}
+
+type Searches = Arc<Mutex<HashMap<String, ActiveSearch>>>;
+
+// Stops the search and forgets it once the request is finished or dropped,
+// the latter happens when the client goes away before the move is ready
+struct SearchGuard {
+    searches: Searches,
+    search_id: Option<String>,
+    stop: StopToken,
+}
+
+impl Drop for SearchGuard {
+    fn drop(&mut self) {
+        self.stop.stop();
+        if let Some(id) = &self.search_id {
+            self.searches.lock().unwrap().remove(id);
+        }
+    }
+}
+
+async fn solve_move(obj: ReqObject, searches: Searches) -> Response<String> {
+    // External brains would run a program given by the client
+    if algo::is_external(&obj.algo_type) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(control::error_message("external engines are not available"))
+            .unwrap();
+    }
+    let width = obj.width.unwrap_or(BOARD_WIDTH);
+    let height = obj.height.unwrap_or(BOARD_HEIGHT);
+    for i in 0..height {
+        for j in 0..width {
+            let c = i * width + j;
+            print!("{}", obj.state.chars().nth(c).unwrap());
+        }
+        println!();
+    }
+
+    let stop = StopToken::new();
+    let progress = Arc::new(Mutex::new(None));
+    if let Some(id) = &obj.search_id {
+        let search = ActiveSearch {
+            stop: stop.clone(),
+            progress: progress.clone(),
+        };
+        searches.lock().unwrap().insert(id.clone(), search);
+    }
+    let _guard = SearchGuard {
+        searches,
+        search_id: obj.search_id.clone(),
+        stop: stop.clone(),
+    };
+    let control = SearchControl::new(
+        stop,
+        Some(Arc::new(move |p: &SearchProgress| {
+            *progress.lock().unwrap() = Some(p.clone());
+        })),
+    );
+    let options = obj.options();
+    let result = tokio::task::spawn_blocking(move || {
+        control::solve_it_with(
+            &obj.state,
+            &obj.algo_type,
+            width,
+            height,
+            &options,
+            &control,
+        )
+    })
+    .await;
+    match result {
+        Ok(body) => Response::builder()
+            .header("Access-Control-Allow-Origin", "*")
+            .body(body)
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(String::from("Search failed."))
+            .unwrap(),
+    }
 }
 
 #[tokio::main]
 pub async fn run_server(port: u16) {
+    println!("listen to : {} ...", port);
+    warp::serve(routes())
+        .run((Ipv4Addr::UNSPECIFIED, port))
+        .await
+}
+
+fn routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let searches: Searches = Arc::new(Mutex::new(HashMap::new()));
+    let with_searches = warp::any().map(move || searches.clone());
+
     let opt_query = warp::query::<ReqObject>()
         .map(Some)
         .or_else(|_| async { Ok::<(Option<ReqObject>,), std::convert::Infallible>((None,)) });
@@ -27,35 +158,97 @@ pub async fn run_server(port: u16) {
         .and(warp::path("api"))
         .and(warp::path("move"))
         .and(opt_query)
-        .map(|p: Option<ReqObject>| match p {
-            Some(obj) => {
-                let width = obj.width.unwrap_or(BOARD_WIDTH);
-                let height = obj.height.unwrap_or(BOARD_HEIGHT);
-                for i in 0..height {
-                    for j in 0..width {
-                        let c = (i * width + j) as usize;
-                        print!("{}", obj.state.chars().nth(c).unwrap());
-                    }
-                    println!();
+        .and(with_searches.clone())
+        .and_then(|p: Option<ReqObject>, searches: Searches| async move {
+            let response = match p {
+                Some(obj) => solve_move(obj, searches).await,
+                None => Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(String::from("Failed to decode query param."))
+                    .unwrap(),
+            };
+            Ok::<_, Infallible>(response)
+        });
+
+    // Ask a running search to play its current best move now
+    let api_stop = warp::get()
+        .and(warp::path("api"))
+        .and(warp::path("stop"))
+        .and(warp::query::<SearchReq>())
+        .and(with_searches.clone())
+        .map(|req: SearchReq, searches: Searches| {
+            match searches.lock().unwrap().get(&req.search_id) {
+                Some(search) => {
+                    search.stop.stop();
+                    Response::builder()
+                        .header("Access-Control-Allow-Origin", "*")
+                        .body(String::from("{\"message\":\"ok\"}"))
+                }
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(String::from("No such search.")),
+            }
+        });
+
+    // Latest progress reported by a running search
+    let api_progress = warp::get()
+        .and(warp::path("api"))
+        .and(warp::path("progress"))
+        .and(warp::query::<SearchReq>())
+        .and(with_searches)
+        .map(|req: SearchReq, searches: Searches| {
+            match searches.lock().unwrap().get(&req.search_id) {
+                Some(search) => {
+                    let progress = search.progress.lock().unwrap();
+                    Response::builder()
+                        .header("Access-Control-Allow-Origin", "*")
+                        .body(serde_json::to_string(&*progress).unwrap())
                 }
-                let result = control::solve_it(&obj.state, &obj.algo_type, width, height);
-                Response::builder()
-                    .header("Access-Control-Allow-Origin", "*")
-                    .body(result)
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(String::from("No such search.")),
             }
-            None => Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body(String::from("Failed to decode query param.")),
+        });
+
+    // Engines which can be passed as `algo_type`
+    let api_engines = warp::get()
+        .and(warp::path("api"))
+        .and(warp::path("engines"))
+        .map(|| {
+            Response::builder()
+                .header("Access-Control-Allow-Origin", "*")
+                .body(control::engines_json())
         });
 
     // Serve static files from the client/ directory at the root path
     let static_files = warp::get().and(dir("../client"));
 
     // Combine API route and static files. API takes precedence.
-    let handler = api_move.or(static_files);
+    api_move
+        .or(api_stop)
+        .or(api_progress)
+        .or(api_engines)
+        .or(static_files)
+}
 
-    println!("listen to : {} ...", port);
-    warp::serve(handler)
-        .run((Ipv4Addr::UNSPECIFIED, port))
-        .await
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_api_move_refuses_external_engines() {
+        let state = "0".repeat(BOARD_WIDTH * BOARD_HEIGHT);
+        let res = warp::test::request()
+            .path(&format!("/api/move?state={}&algo_type=pbrain:true", state))
+            .reply(&routes())
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(res.body()).contains("not available"));
+
+        let res = warp::test::request()
+            .path(&format!("/api/move?state={}&algo_type=one_ply", state))
+            .reply(&routes())
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,918 @@
+#![allow(dead_code)]
+// Exhaustive solver and a cache of solved positions for small boards (up to 8x8)
+use super::algo::{create_engine, Engine, EngineOptions, UnknownEngine};
+use super::board::*;
+use super::rng::GameRng;
+use super::search::{SearchControl, SearchLimits, SearchResult, SearchStats, StopToken, Timer};
+use super::utils::cfg;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+
+const WIN: i8 = 1;
+const DRAW: i8 = 0;
+const LOSS: i8 = -1;
+
+const EXACT: u8 = 0;
+const LOWER: u8 = 1;
+const UPPER: u8 = 2;
+
+const SOLVER_CAPACITY: usize = 1 << 23;
+
+const CACHE_MAGIC: &[u8; 4] = b"GMSC";
+// Bytes of a cache entry on disk: key, value and flag
+const CACHE_ENTRY: usize = 18;
+// Positions searched between two looks at the limits
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Something the solver can not do
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolverError {
+    /// Boards need at most 64 cells, a side of at least 5 and a line of at least one stone
+    Unsupported {
+        width: usize,
+        height: usize,
+        win_len: usize,
+    },
+    /// A board or cache of other rules than the solver's, as width, height and line length
+    Mismatch {
+        expected: (usize, usize, usize),
+        found: (usize, usize, usize),
+    },
+    UnknownEngine(UnknownEngine),
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolverError::Unsupported {
+                width,
+                height,
+                win_len,
+            } => write!(
+                f,
+                "the solver can not play {}x{} with {} in a row",
+                width, height, win_len
+            ),
+            SolverError::Mismatch { expected, found } => write!(
+                f,
+                "the solver is built for {}x{} with {} in a row, not {}x{} with {}",
+                expected.0, expected.1, expected.2, found.0, found.1, found.2
+            ),
+            SolverError::UnknownEngine(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SolverError {}
+
+impl From<UnknownEngine> for SolverError {
+    fn from(e: UnknownEngine) -> Self {
+        SolverError::UnknownEngine(e)
+    }
+}
+
+/// Game theoretic value of a position for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl Outcome {
+    fn from_value(value: i8) -> Self {
+        match value {
+            WIN => Outcome::Win,
+            LOSS => Outcome::Loss,
+            _ => Outcome::Draw,
+        }
+    }
+
+    fn value(self) -> i8 {
+        match self {
+            Outcome::Win => WIN,
+            Outcome::Draw => DRAW,
+            Outcome::Loss => LOSS,
+        }
+    }
+}
+
+/* Positions are two bitboards, the stones of the side to move and of the
+ * opponent. The side to move follows from the stone count, so the pair is
+ * enough to identify a position. Keys are the smallest pair over all
+ * symmetries of the board, which makes them exact and canonical.
+ * A search which runs out of its limits gives up, see `Solver::set_limits`,
+ * and nothing it found is kept.
+ */
+#[derive(Debug, Clone)]
+pub struct Solver {
+    pub width: usize,
+    pub height: usize,
+    pub win_len: usize,
+    // Every run of `win_len` cells a player can win on
+    lines: Vec<u64>,
+    // Cell permutation of every symmetry which keeps the board shape
+    syms: Vec<Vec<usize>>,
+    // Cells ordered from the center outwards
+    order: Vec<usize>,
+    table: HashMap<u128, (i8, u8)>,
+    /// Bounds are only remembered while the table is smaller than this, exact values always are
+    pub capacity: usize,
+    pub nodes: u64,
+    limits: SearchLimits,
+    stop: StopToken,
+    timer: Timer,
+    // `nodes` when the current search started, the node limit counts from there
+    start_nodes: u64,
+    aborted: bool,
+}
+
+impl Solver {
+    pub fn new(width: usize, height: usize, win_len: usize) -> Result<Self, SolverError> {
+        if width * height > 64 || (width < 5 && height < 5) || win_len == 0 {
+            return Err(SolverError::Unsupported {
+                width,
+                height,
+                win_len,
+            });
+        }
+        let board = Board::new("0".repeat(width * height), width, height);
+        let mut lines = vec![];
+        for i in 0..height as i32 {
+            for j in 0..width as i32 {
+                for d in cfg::DIRS.iter() {
+                    let cells: Vec<(i32, i32)> = (0..win_len as i32)
+                        .map(|k| (i + d[0] * k, j + d[1] * k))
+                        .collect();
+                    if cells.iter().all(|&(r, c)| board.get(r, c).is_some()) {
+                        lines.push(
+                            cells
+                                .iter()
+                                .fold(0, |m, &(r, c)| m | 1 << (r as usize * width + c as usize)),
+                        );
+                    }
+                }
+            }
+        }
+        let syms = (0..8)
+            .filter(|sym| board.has_symmetry(*sym))
+            .map(|sym| {
+                (0..width * height)
+                    .map(|cell| {
+                        let (r, c) = board.transform(sym, cell / width, cell % width);
+                        r * width + c
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..width * height).collect();
+        let (cr, cc) = ((height - 1) as i32, (width - 1) as i32);
+        order.sort_by_key(|cell| {
+            let (r, c) = ((cell / width) as i32 * 2, (cell % width) as i32 * 2);
+            (r - cr).abs().max((c - cc).abs()) * 100 + (r - cr).abs() + (c - cc).abs()
+        });
+        Ok(Self {
+            width,
+            height,
+            win_len,
+            lines,
+            syms,
+            order,
+            table: HashMap::new(),
+            capacity: SOLVER_CAPACITY,
+            nodes: 0,
+            limits: SearchLimits::default(),
+            stop: StopToken::new(),
+            timer: Timer::start(),
+            start_nodes: 0,
+            aborted: false,
+        })
+    }
+
+    /// Time and node budget of the next searches and the token stopping them.
+    /// The default has no limits.
+    pub fn set_limits(&mut self, limits: SearchLimits, stop: StopToken) {
+        self.limits = limits;
+        self.stop = stop;
+    }
+
+    /// Whether the last search gave up before it was done
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Solve `board` completely, returns the outcome for the side to move.
+    /// The outcome of an aborted search means nothing.
+    pub fn solve(&mut self, board: &Board) -> Result<Outcome, SolverError> {
+        let (me, opp) = self.masks(board)?;
+        self.start();
+        if self.has_won(opp) {
+            return Ok(Outcome::Loss);
+        }
+        Ok(Outcome::from_value(self.negamax(me, opp, LOSS, WIN)))
+    }
+
+    /// All empty cells with the outcome for the side to move after playing there.
+    /// An aborted search only has the cells solved before it gave up.
+    pub fn move_outcomes(&mut self, board: &Board) -> Result<Vec<(Move, Outcome)>, SolverError> {
+        let (me, opp) = self.masks(board)?;
+        self.start();
+        let empty = self.full() & !(me | opp);
+        let mut res = vec![];
+        for &cell in self.order.clone().iter() {
+            if empty & 1 << cell == 0 {
+                continue;
+            }
+            let me = me | 1 << cell;
+            let value = if self.has_won(me) {
+                WIN
+            } else {
+                -self.negamax(opp, me, LOSS, WIN)
+            };
+            if self.aborted {
+                break;
+            }
+            let mv = Move::new(cell / self.width, cell % self.width, value as i32, 0);
+            res.push((mv, Outcome::from_value(value)));
+        }
+        Ok(res)
+    }
+
+    /// Best move for the side to move, an immediate win is preferred over a slower one.
+    /// An aborted search answers the best of the cells it solved, or the most central one.
+    pub fn best_move(&mut self, board: &Board) -> Result<Option<Move>, SolverError> {
+        let (me, opp) = self.masks(board)?;
+        if self.has_won(opp) {
+            return Ok(None);
+        }
+        let wins = self.winning_cells(me, opp);
+        if wins != 0 {
+            let cell = wins.trailing_zeros() as usize;
+            return Ok(Some(Move::new(
+                cell / self.width,
+                cell % self.width,
+                WIN as i32,
+                0,
+            )));
+        }
+        let best = self
+            .move_outcomes(board)?
+            .into_iter()
+            .max_by_key(|(_, outcome)| *outcome)
+            .map(|(mv, _)| mv);
+        let empty = self.full() & !(me | opp);
+        Ok(best.or_else(|| {
+            self.order
+                .iter()
+                .find(|&&cell| empty & 1 << cell != 0)
+                .map(|&cell| Move::new(cell / self.width, cell % self.width, 0, 0))
+        }))
+    }
+
+    fn start(&mut self) {
+        self.timer = Timer::start();
+        self.start_nodes = self.nodes;
+        self.aborted = false;
+    }
+
+    // Whether the search was stopped or used up its limits
+    fn should_stop(&self) -> bool {
+        self.stop.is_stopped()
+            || self
+                .limits
+                .time_ms
+                .is_some_and(|t| self.timer.elapsed_ms() >= t)
+            || self
+                .limits
+                .nodes
+                .is_some_and(|n| self.nodes - self.start_nodes >= n)
+    }
+
+    fn negamax(&mut self, me: u64, opp: u64, mut alpha: i8, mut beta: i8) -> i8 {
+        if self.aborted {
+            return DRAW;
+        }
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) && self.should_stop() {
+            self.aborted = true;
+            return DRAW;
+        }
+        let empty = self.full() & !(me | opp);
+        if empty == 0 {
+            return DRAW;
+        }
+        if self.winning_cells(me, opp) != 0 {
+            return WIN;
+        }
+        let threats = self.winning_cells(opp, me);
+        if threats.count_ones() > 1 {
+            return LOSS;
+        }
+
+        let key = self.key(me, opp);
+        let alpha_orig = alpha;
+        if let Some(&(value, flag)) = self.table.get(&key) {
+            match flag {
+                EXACT => return value,
+                LOWER => alpha = alpha.max(value),
+                _ => beta = beta.min(value),
+            }
+            if alpha >= beta {
+                return value;
+            }
+        }
+
+        let mut moves = [0usize; 64];
+        let mut count = 0;
+        if threats != 0 {
+            // A single threat has to be blocked
+            moves[0] = threats.trailing_zeros() as usize;
+            count = 1;
+        } else {
+            // Moves which make a threat of our own are tried first
+            let mut attacks = 0u64;
+            for &cell in self.order.iter() {
+                if empty & 1 << cell != 0 && self.winning_cells(me | 1 << cell, opp) != 0 {
+                    attacks |= 1 << cell;
+                    moves[count] = cell;
+                    count += 1;
+                }
+            }
+            for &cell in self.order.iter() {
+                if empty & !attacks & 1 << cell != 0 {
+                    moves[count] = cell;
+                    count += 1;
+                }
+            }
+        }
+
+        let mut best = LOSS;
+        for &cell in moves[..count].iter() {
+            let value = -self.negamax(opp, me | 1 << cell, -beta, -alpha);
+            if self.aborted {
+                return DRAW;
+            }
+            best = best.max(value);
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // A win can not be improved on and a loss can not get worse, so those bounds are exact
+        let flag = if best == WIN || best == LOSS {
+            EXACT
+        } else if best <= alpha_orig {
+            UPPER
+        } else if best >= beta {
+            LOWER
+        } else {
+            EXACT
+        };
+        if self.table.len() < self.capacity || flag == EXACT {
+            self.table.insert(key, (best, flag));
+        }
+        best
+    }
+
+    // Mask of the empty cells which complete a line of `me`
+    fn winning_cells(&self, me: u64, opp: u64) -> u64 {
+        let mut cells = 0;
+        for &line in self.lines.iter() {
+            if line & opp == 0 && (line & me).count_ones() as usize == self.win_len - 1 {
+                cells |= line & !me;
+            }
+        }
+        cells
+    }
+
+    fn has_won(&self, stones: u64) -> bool {
+        !self.lines.iter().all(|&line| line & !stones != 0)
+    }
+
+    fn full(&self) -> u64 {
+        u64::MAX >> (64 - self.width * self.height)
+    }
+
+    fn rules(&self) -> (usize, usize, usize) {
+        (self.width, self.height, self.win_len)
+    }
+
+    fn masks(&self, board: &Board) -> Result<(u64, u64), SolverError> {
+        let found = (board.width, board.height, board.win_len);
+        if found != self.rules() {
+            return Err(SolverError::Mismatch {
+                expected: self.rules(),
+                found,
+            });
+        }
+        let (mut p1, mut p2) = (0u64, 0u64);
+        for cell in 0..self.width * self.height {
+            match board.get((cell / self.width) as i32, (cell % self.width) as i32) {
+                Some(1) => p1 |= 1 << cell,
+                Some(2) => p2 |= 1 << cell,
+                _ => (),
+            }
+        }
+        match board.next_player() {
+            1 => Ok((p1, p2)),
+            _ => Ok((p2, p1)),
+        }
+    }
+
+    fn key(&self, me: u64, opp: u64) -> u128 {
+        self.syms
+            .iter()
+            .map(|perm| (permute(perm, me) as u128) << 64 | permute(perm, opp) as u128)
+            .min()
+            .unwrap()
+    }
+
+    /// Everything the solver has learned so far, see `EndgameCache`
+    pub fn cache(&self) -> EndgameCache {
+        let mut entries: Vec<(u128, i8, u8)> = self
+            .table
+            .iter()
+            .map(|(key, (value, flag))| (*key, *value, *flag))
+            .collect();
+        entries.sort_unstable();
+        EndgameCache {
+            width: self.width,
+            height: self.height,
+            win_len: self.win_len,
+            entries,
+        }
+    }
+
+    /// Seed the table with a cache, so solving the positions in it again is instant
+    pub fn load_cache(&mut self, cache: &EndgameCache) -> Result<(), SolverError> {
+        let found = (cache.width, cache.height, cache.win_len);
+        if found != self.rules() {
+            return Err(SolverError::Mismatch {
+                expected: self.rules(),
+                found,
+            });
+        }
+        for (key, value, flag) in cache.entries.iter() {
+            self.table.insert(*key, (*value, *flag));
+        }
+        Ok(())
+    }
+}
+
+fn permute(perm: &[usize], mut stones: u64) -> u64 {
+    let mut res = 0;
+    while stones != 0 {
+        let cell = stones.trailing_zeros() as usize;
+        res |= 1 << perm[cell];
+        stones &= stones - 1;
+    }
+    res
+}
+
+/* The transposition table of a solver, sorted by canonical key. It holds the
+ * positions the alpha-beta search came across, not every reachable position:
+ * the cut off moves are never looked at, and the positions off the principal
+ * variation often only have a bound. Exact values are kept whatever the size,
+ * bounds only up to the capacity of the solver. Loading it into a solver
+ * spares the search, anything missing is solved on demand.
+ * Stored on disk as a small header followed by 18 bytes per position.
+ */
+#[derive(Debug, Clone)]
+pub struct EndgameCache {
+    pub width: usize,
+    pub height: usize,
+    pub win_len: usize,
+    entries: Vec<(u128, i8, u8)>,
+}
+
+impl EndgameCache {
+    /// Solve the game from the empty board
+    pub fn build(
+        width: usize,
+        height: usize,
+        win_len: usize,
+    ) -> Result<(Self, Outcome), SolverError> {
+        let mut solver = Solver::new(width, height, win_len)?;
+        let mut board = Board::new("0".repeat(width * height), width, height);
+        board.win_len = win_len;
+        let outcome = solver.solve(&board)?;
+        Ok((solver.cache(), outcome))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of positions whose value is known exactly
+    pub fn exact_len(&self) -> usize {
+        self.entries.iter().filter(|e| e.2 == EXACT).count()
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(8 + self.entries.len() * CACHE_ENTRY);
+        buf.extend_from_slice(CACHE_MAGIC);
+        buf.extend_from_slice(&[self.width as u8, self.height as u8, self.win_len as u8, 0]);
+        for (key, value, flag) in self.entries.iter() {
+            buf.extend_from_slice(&key.to_le_bytes());
+            buf.push(*value as u8);
+            buf.push(*flag);
+        }
+        fs::File::create(path)?.write_all(&buf)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut buf = vec![];
+        fs::File::open(path)?.read_to_end(&mut buf)?;
+        if buf.len() < 8 || &buf[0..4] != CACHE_MAGIC || (buf.len() - 8) % CACHE_ENTRY != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an endgame cache",
+            ));
+        }
+        let entries = buf[8..]
+            .chunks(CACHE_ENTRY)
+            .map(|e| {
+                let mut key = [0u8; 16];
+                key.copy_from_slice(&e[0..16]);
+                (u128::from_le_bytes(key), e[16] as i8, e[17])
+            })
+            .collect();
+        Ok(Self {
+            width: buf[4] as usize,
+            height: buf[5] as usize,
+            win_len: buf[6] as usize,
+            entries,
+        })
+    }
+}
+
+/* The "endgame" engine, plays perfectly on boards of at most 64 cells and
+ * answers no move on larger ones. Positions missing from the cache it starts
+ * with are solved on demand, what it learns is kept from game to game since
+ * the values do not change. A search which runs out of its limits plays the
+ * best move it has solved so far.
+ */
+pub struct EndgameEngine {
+    // Built for the rules of the position, a position of other rules replaces it
+    solver: Option<Solver>,
+    board: Board,
+    control: SearchControl,
+}
+
+impl EndgameEngine {
+    /// An engine which solves every position on demand
+    pub fn new() -> Self {
+        Self {
+            solver: None,
+            board: Board::new_default(),
+            control: SearchControl::default(),
+        }
+    }
+
+    /// An engine which starts from `cache`
+    pub fn with_cache(cache: &EndgameCache) -> Result<Self, SolverError> {
+        let mut solver = Solver::new(cache.width, cache.height, cache.win_len)?;
+        solver.load_cache(cache)?;
+        Ok(Self {
+            solver: Some(solver),
+            ..Self::new()
+        })
+    }
+
+    // The solver for the rules of `board`
+    fn solver(&mut self, board: &Board) -> Result<&mut Solver, SolverError> {
+        let rules = (board.width, board.height, board.win_len);
+        if self.solver.as_ref().map(|s| s.rules()) != Some(rules) {
+            self.solver = Some(Solver::new(board.width, board.height, board.win_len)?);
+        }
+        Ok(self.solver.as_mut().unwrap())
+    }
+
+    pub fn best_move(&mut self, board: &Board) -> Result<Option<Move>, SolverError> {
+        let solver = self.solver(board)?;
+        solver.set_limits(SearchLimits::default(), StopToken::new());
+        solver.best_move(board)
+    }
+
+    pub fn outcome(&mut self, board: &Board) -> Result<Outcome, SolverError> {
+        let solver = self.solver(board)?;
+        solver.set_limits(SearchLimits::default(), StopToken::new());
+        solver.solve(board)
+    }
+
+    /// Whether `mv` keeps the best outcome available on `board`
+    pub fn is_optimal(&mut self, board: &Board, mv: &Move) -> Result<bool, SolverError> {
+        let best = self.outcome(board)?;
+        let mut next = board.clone();
+        next.place(mv.x, mv.y, board.next_player());
+        let solver = self.solver(&next)?;
+        let (_, opp) = solver.masks(&next)?;
+        let after = if solver.has_won(opp) {
+            Outcome::Win
+        } else {
+            Outcome::from_value(-solver.solve(&next)?.value())
+        };
+        Ok(after == best)
+    }
+}
+
+impl Default for EndgameEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine for EndgameEngine {
+    fn name(&self) -> &str {
+        "endgame"
+    }
+
+    fn new_game(&mut self) {
+        self.board = Board::new_default();
+    }
+
+    fn set_position(&mut self, board: &Board) {
+        self.board = board.clone();
+    }
+
+    fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    fn stop_token(&self) -> StopToken {
+        self.control.stop.clone()
+    }
+
+    fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        let timer = Timer::start();
+        let board = self.board.clone();
+        let stop = self.control.stop.clone();
+        let solver = match self.solver(&board) {
+            Ok(solver) => solver,
+            Err(_) => return SearchResult::no_move(SearchStats::default(), *limits),
+        };
+        solver.set_limits(*limits, stop);
+        let nodes = solver.nodes;
+        let best = solver.best_move(&board).ok().flatten();
+        let stats = SearchStats {
+            // A finished search looked all the way to the full board
+            depth: if solver.aborted() {
+                0
+            } else {
+                board.empty_cells_count() as i32
+            },
+            nodes: solver.nodes - nodes,
+            evaluations: solver.nodes - nodes,
+            elapsed_ms: timer.elapsed_ms(),
+            threads: 1,
+            ..SearchStats::default()
+        };
+        match best {
+            Some(best_move) => SearchResult {
+                best_move,
+                stats,
+                limits: *limits,
+            },
+            None => SearchResult::no_move(stats, *limits),
+        }
+    }
+}
+
+/// How often an engine gave away a better outcome
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    pub positions: u32,
+    pub suboptimal: u32,
+}
+
+impl AuditReport {
+    pub fn error_rate(&self) -> f32 {
+        if self.positions == 0 {
+            return 0.0;
+        }
+        self.suboptimal as f32 / self.positions as f32
+    }
+}
+
+/// Compare the moves of `algo_type` with perfect play on `positions`
+pub fn audit_engine(
+    engine: &mut EndgameEngine,
+    algo_type: &str,
+    positions: &[Board],
+) -> Result<AuditReport, SolverError> {
+    let mut report = AuditReport::default();
+    let mut searcher = create_engine(algo_type, &EngineOptions::default())?;
+    for board in positions {
+        if board.any_winner().is_some() || board.empty_cells_count() == 0 {
+            continue;
+        }
+        searcher.new_game();
+        searcher.set_position(board);
+        let mv = searcher.search(&SearchLimits::default()).best_move;
+        report.positions += 1;
+        if board.get(mv.x as i32, mv.y as i32) != Some(0) || !engine.is_optimal(board, &mv)? {
+            report.suboptimal += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Positions reached by random play on an empty board, used to audit engines.
+/// Without the `random` feature the cells are filled from the center outwards,
+/// one more with every position.
+pub fn sample_positions(
+    width: usize,
+    height: usize,
+    win_len: usize,
+    count: usize,
+    rng: &mut GameRng,
+) -> Result<Vec<Board>, SolverError> {
+    let solver = Solver::new(width, height, win_len)?;
+    let cells = width * height;
+    // Number of stones is 2 up to half the board
+    let span = (cells / 2).saturating_sub(2).max(1);
+    let mut positions = vec![];
+    let mut tries = 0;
+    while positions.len() < count && tries < count * 10 {
+        tries += 1;
+        let mut board = Board::new("0".repeat(cells), width, height);
+        board.win_len = win_len;
+        let plies = 2 + (tries + rng.below(span)) % span;
+        for _ in 0..plies {
+            let empty: Vec<usize> = solver
+                .order
+                .iter()
+                .copied()
+                .filter(|c| board.get((c / width) as i32, (c % width) as i32) == Some(0))
+                .collect();
+            if empty.is_empty() {
+                break;
+            }
+            let cell = empty[rng.below(empty.len())];
+            board.place(cell / width, cell % width, board.next_player());
+        }
+        if board.any_winner().is_none() {
+            positions.push(board);
+        }
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_board(input: &str) -> Board {
+        let mut board = Board::new(input.to_string(), 5, 5);
+        board.win_len = 4;
+        board
+    }
+
+    #[test]
+    fn test_solver_finds_win() {
+        let mut solver = Solver::new(5, 5, 4).unwrap();
+        // Player 1 has an open three and wins
+        let board = small_board(
+            "00000\
+             01110\
+             02200\
+             00000\
+             00020",
+        );
+        assert_eq!(solver.solve(&board).unwrap(), Outcome::Win);
+        let mv = solver.best_move(&board).unwrap().unwrap();
+        assert!((mv.x, mv.y) == (1, 0) || (mv.x, mv.y) == (1, 4));
+    }
+
+    #[test]
+    fn test_endgame_cache_roundtrip() {
+        let mut solver = Solver::new(5, 5, 4).unwrap();
+        let board = small_board(
+            "00000\
+             01020\
+             02100\
+             00000\
+             00000",
+        );
+        let outcome = solver.solve(&board).unwrap();
+        let cache = solver.cache();
+        assert!(!cache.is_empty());
+
+        let path = std::env::temp_dir().join("gomoku_test_endgame.cache");
+        let path = path.to_str().unwrap();
+        cache.save(path).unwrap();
+        let loaded = EndgameCache::load(path).unwrap();
+        fs::remove_file(path).ok();
+        assert_eq!(loaded.len(), cache.len());
+        assert_eq!(loaded.exact_len(), cache.exact_len());
+
+        // The engine answers from the cache without searching again
+        let mut engine = EndgameEngine::with_cache(&loaded).unwrap();
+        assert_eq!(engine.outcome(&board).unwrap(), outcome);
+        assert!(engine.solver.as_ref().unwrap().nodes <= 1);
+        let mv = engine.best_move(&board).unwrap().unwrap();
+        assert!(engine.is_optimal(&board, &mv).unwrap());
+    }
+
+    #[test]
+    fn test_endgame_cache_off_pv() {
+        let board = small_board(
+            "00000\
+             00000\
+             00120\
+             00000\
+             00000",
+        );
+        let mut solver = Solver::new(5, 5, 4).unwrap();
+        solver.solve(&board).unwrap();
+        let cache = solver.cache();
+        let best = Solver::new(5, 5, 4)
+            .unwrap()
+            .best_move(&board)
+            .unwrap()
+            .unwrap();
+
+        // Positions after moves other than the best one are cached too
+        let (me, opp) = solver.masks(&board).unwrap();
+        let off_pv = (0..25)
+            .filter(|&cell| (me | opp) & 1 << cell == 0 && cell != best.x * 5 + best.y)
+            .map(|cell| solver.key(opp, me | 1 << cell))
+            .filter(|key| cache.entries.binary_search_by_key(key, |e| e.0).is_ok())
+            .count();
+        assert!(off_pv > 0);
+        assert!(cache.exact_len() < cache.len());
+    }
+
+    #[test]
+    fn test_solver_errors() {
+        assert!(matches!(
+            Solver::new(9, 9, 5),
+            Err(SolverError::Unsupported { .. })
+        ));
+        let mut solver = Solver::new(5, 5, 4).unwrap();
+        let err = solver.solve(&Board::new_default()).unwrap_err();
+        assert_eq!(
+            err,
+            SolverError::Mismatch {
+                expected: (5, 5, 4),
+                found: (15, 15, 5),
+            }
+        );
+        assert!(Solver::new(4, 4, 3).is_err());
+        let (cache, _) = EndgameCache::build(5, 3, 3).unwrap();
+        assert!(solver.load_cache(&cache).is_err());
+        assert!(EndgameEngine::with_cache(&cache).is_ok());
+        assert!(sample_positions(20, 20, 5, 1, &mut GameRng::new(1)).is_err());
+    }
+
+    #[test]
+    fn test_endgame_engine() {
+        let board = small_board(
+            "00000\
+             01110\
+             02200\
+             00000\
+             00020",
+        );
+        let mut engine = create_engine("endgame", &EngineOptions::default()).unwrap();
+        engine.set_position(&board);
+        let res = engine.search(&SearchLimits::default());
+        assert!([(1, 0), (1, 4)].contains(&(res.best_move.x, res.best_move.y)));
+        assert_eq!(res.stats.threads, 1);
+
+        // A stopped search still answers an empty cell
+        let board = small_board(&"0".repeat(25));
+        let stop = StopToken::new();
+        stop.stop();
+        engine.set_control(SearchControl::new(stop, None));
+        engine.set_position(&board);
+        let res = engine.search(&SearchLimits::default());
+        assert_eq!(
+            board.get(res.best_move.x as i32, res.best_move.y as i32),
+            Some(0)
+        );
+
+        // Boards over 64 cells get no move
+        engine.set_control(SearchControl::default());
+        engine.set_position(&Board::new_default());
+        assert!(!engine.search(&SearchLimits::default()).has_move());
+    }
+
+    #[test]
+    fn test_sample_positions_seed() {
+        let first = sample_positions(5, 5, 4, 10, &mut GameRng::new(7)).unwrap();
+        let second = sample_positions(5, 5, 4, 10, &mut GameRng::new(7)).unwrap();
+        assert_eq!(first.len(), 10);
+        let strings = |positions: &[Board]| -> Vec<String> {
+            positions.iter().map(|b| b.to_string()).collect()
+        };
+        assert_eq!(strings(&first), strings(&second));
+    }
+}
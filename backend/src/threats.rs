@@ -0,0 +1,264 @@
+#![allow(dead_code)]
+// Threat search - forced wins by continuous fours (VCF)
+use super::board::*;
+use super::search::{SearchControl, Timer};
+use super::utils::*;
+use std::collections::HashSet;
+
+/// Empty cells where `player` completes a line of `win_len` stones
+pub fn winning_cells(board: &Board, player: u8) -> Vec<(usize, usize)> {
+    window_cells(board, player, board.win_len - 1)
+}
+
+/// Empty cells where `player` makes a four, i.e. a line one stone short of a win
+/// which the opponent has to block. Cells making several fours come first.
+pub fn four_moves(board: &Board, player: u8) -> Vec<(usize, usize)> {
+    window_cells(board, player, board.win_len - 2)
+}
+
+// Empty cells of the `win_len` long windows holding exactly `stones` stones of
+// `player` and no stone of the opponent, the cells in most windows first
+fn window_cells(board: &Board, player: u8, stones: usize) -> Vec<(usize, usize)> {
+    let len = board.win_len as i32;
+    let mut counts: Vec<((usize, usize), usize)> = vec![];
+    for d in cfg::DIRS.iter() {
+        for row in 0..board.height as i32 {
+            for col in 0..board.width as i32 {
+                let end = (row + d[0] * (len - 1), col + d[1] * (len - 1));
+                if board.get(end.0, end.1).is_none() {
+                    continue;
+                }
+                let mut mine = 0;
+                let mut empty = vec![];
+                for k in 0..len {
+                    let (r, c) = (row + d[0] * k, col + d[1] * k);
+                    match board.get(r, c) {
+                        Some(0) => empty.push((r as usize, c as usize)),
+                        Some(p) if p == player => mine += 1,
+                        _ => {
+                            mine = usize::MAX;
+                            break;
+                        }
+                    }
+                }
+                // With exact fives a stone next to the window makes an overline
+                let before = board.get(row - d[0], col - d[1]);
+                let after = board.get(end.0 + d[0], end.1 + d[1]);
+                if mine != stones
+                    || (board.exact_five && (before == Some(player) || after == Some(player)))
+                {
+                    continue;
+                }
+                for cell in empty {
+                    match counts.iter_mut().find(|(c, _)| *c == cell) {
+                        Some(entry) => entry.1 += 1,
+                        None => counts.push((cell, 1)),
+                    }
+                }
+            }
+        }
+    }
+    counts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+    counts.into_iter().map(|(cell, _)| cell).collect()
+}
+
+/* Searches for a win by continuous fours: every move of the attacker makes a
+ * four, so the defender's reply is forced, until the attacker gets two fours
+ * at once or the defender cannot block. The search is bounded by the number
+ * of fours, the positions looked at and an optional time limit.
+ */
+pub struct ThreatSolver {
+    /// Most fours the attacker may play
+    pub max_depth: usize,
+    /// Positions looked at before the search gives up
+    pub max_nodes: u64,
+    time_ms: Option<u64>,
+    timer: Timer,
+    control: SearchControl,
+    nodes: u64,
+    // Positions and remaining depths known to have no VCF
+    failed: HashSet<(u64, usize)>,
+}
+
+impl Default for ThreatSolver {
+    fn default() -> Self {
+        Self::new(12, 20000)
+    }
+}
+
+impl ThreatSolver {
+    pub fn new(max_depth: usize, max_nodes: u64) -> Self {
+        Self {
+            max_depth,
+            max_nodes,
+            time_ms: None,
+            timer: Timer::start(),
+            control: SearchControl::default(),
+            nodes: 0,
+            failed: HashSet::new(),
+        }
+    }
+
+    /// Stop token checked while searching
+    pub fn set_control(&mut self, control: SearchControl) {
+        self.control = control;
+    }
+
+    /// Give up after `time_ms` milliseconds, `None` for no limit
+    pub fn set_time_limit(&mut self, time_ms: Option<u64>) {
+        self.time_ms = time_ms;
+    }
+
+    /// Positions looked at by the last search
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Moves of `player` winning by continuous fours, the first one is to be played
+    /// now and the last one wins or makes two fours at once
+    pub fn find_vcf(&mut self, board: &Board, player: u8) -> Option<Vec<(usize, usize)>> {
+        self.nodes = 0;
+        self.timer = Timer::start();
+        self.failed.clear();
+        let mut board = board.clone();
+        let mut line = vec![];
+        if self.vcf(&mut board, player, self.max_depth, &mut line) {
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    fn should_stop(&self) -> bool {
+        self.nodes >= self.max_nodes
+            || self.control.is_stopped()
+            || self.time_ms.is_some_and(|t| self.timer.elapsed_ms() >= t)
+    }
+
+    fn vcf(
+        &mut self,
+        board: &mut Board,
+        attacker: u8,
+        depth: usize,
+        line: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        self.nodes += 1;
+        if let Some(&cell) = winning_cells(board, attacker).first() {
+            line.push(cell);
+            return true;
+        }
+        let key = (board.hash(), depth);
+        if depth == 0 || self.should_stop() || self.failed.contains(&key) {
+            return false;
+        }
+        let defender = cfg::opponent(attacker);
+        // A five of the defender has to be blocked, only a block making a four keeps the initiative
+        let their_wins = winning_cells(board, defender);
+        for (row, col) in four_moves(board, attacker) {
+            if !their_wins.is_empty() && !their_wins.contains(&(row, col)) {
+                continue;
+            }
+            board.place(row, col, attacker);
+            line.push((row, col));
+            let blocks = winning_cells(board, attacker);
+            let found = if !winning_cells(board, defender).is_empty() {
+                false
+            } else if blocks.len() >= 2 {
+                true
+            } else {
+                let (r, c) = blocks[0];
+                board.place(r, c, defender);
+                let found = self.vcf(board, attacker, depth - 1, line);
+                board.place(r, c, 0);
+                found
+            };
+            board.place(row, col, 0);
+            if found {
+                return true;
+            }
+            line.pop();
+        }
+        self.failed.insert(key);
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Row 2, row 5 and column 5 make fours, the defender has blocked one end of each
+    pub(crate) fn vcf_board() -> Board {
+        let mut board = Board::new_default();
+        let attacker = [
+            (2, 2),
+            (2, 3),
+            (2, 4),
+            (3, 5),
+            (4, 5),
+            (5, 2),
+            (5, 3),
+            (5, 4),
+        ];
+        let defender = [
+            (2, 1),
+            (5, 1),
+            (6, 5),
+            (14, 10),
+            (14, 12),
+            (14, 14),
+            (12, 14),
+            (10, 14),
+        ];
+        for (r, c) in attacker {
+            board.place(r, c, 1);
+        }
+        for (r, c) in defender {
+            board.place(r, c, 2);
+        }
+        board
+    }
+
+    #[test]
+    fn test_winning_cells_and_fours() {
+        let mut board = Board::new_default();
+        board.place(7, 7, 1);
+        board.place(7, 8, 1);
+        board.place(7, 9, 1);
+        assert!(winning_cells(&board, 1).is_empty());
+        let fours = four_moves(&board, 1);
+        assert!(fours.contains(&(7, 10)) && fours.contains(&(7, 6)));
+        board.place(7, 10, 1);
+        board.place(7, 6, 2);
+        assert_eq!(winning_cells(&board, 1), vec![(7, 11)]);
+        assert!(winning_cells(&board, 2).is_empty());
+    }
+
+    #[test]
+    fn test_find_vcf() {
+        let board = vcf_board();
+        let mut solver = ThreatSolver::default();
+        let line = solver.find_vcf(&board, 1).unwrap();
+        assert_eq!(line.len(), 2);
+        assert!(solver.nodes() > 0);
+
+        // Play the line out, the defender always blocks
+        let mut board = board;
+        for &(r, c) in line.iter() {
+            board.place(r, c, 1);
+            let blocks = winning_cells(&board, 1);
+            assert!(!blocks.is_empty());
+            if blocks.len() == 1 {
+                board.place(blocks[0].0, blocks[0].1, 2);
+            }
+        }
+        assert!(!winning_cells(&board, 1).is_empty());
+
+        // The only four of the defender leads nowhere
+        assert!(solver.find_vcf(&vcf_board(), 2).is_none());
+        // A single four is not enough
+        assert!(ThreatSolver::new(1, 1000)
+            .find_vcf(&vcf_board(), 1)
+            .is_none());
+    }
+}
@@ -27,5 +27,7 @@ pub mod cfg {
 // and Connect4-style (6x7, connect4).
 pub const BOARD_WIDTH: usize = 15;
 pub const BOARD_HEIGHT: usize = 15;
+// Largest board width or height the protocol front ends start a game with
+pub const MAX_BOARD_SIZE: usize = 100;
 #[cfg(test)]
 pub const WIN_LEN: usize = 5;
@@ -19,7 +19,8 @@ fn run_from_data_dir(data_dir: &str, algo_type: &str) {
                 let mut board = board::Board::new(content.clone(), BOARD_WIDTH, BOARD_HEIGHT);
                 board.print();
                 let player = board.next_player();
-                let mv = algo::gomoku_solve(content.as_str(), algo_type, BOARD_WIDTH, BOARD_HEIGHT);
+                let mv = algo::gomoku_solve(content.as_str(), algo_type, BOARD_WIDTH, BOARD_HEIGHT)
+                    .unwrap();
                 println!("move: {:?}", mv);
                 let row = mv.x;
                 let col = mv.y;
@@ -0,0 +1,118 @@
+extern crate gomoku;
+use gomoku::algo::{create_engine_with_external, Engine, EngineOptions};
+use gomoku::board::Board;
+use gomoku::piskvork::{BrainError, ExternalEngine, EXTERNAL_PREFIX};
+use gomoku::search::SearchLimits;
+use gomoku::self_play::SelfPlay;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const STUB: &str = env!("CARGO_BIN_EXE_pbrain-stub");
+const BRAIN: &str = env!("CARGO_BIN_EXE_pbrain-gomoku");
+
+#[test]
+fn test_external_stub() {
+    let mut engine = ExternalEngine::new("stub", STUB);
+    assert!(engine.about().unwrap().contains("stub"));
+
+    // The stub plays the first empty cell, x is the column
+    let mut board = Board::new_default();
+    board.place(0, 0, 1);
+    board.place(0, 1, 2);
+    board.place(7, 7, 1);
+    engine.set_position(&board);
+    let mv = engine.search(&SearchLimits::time(500)).best_move;
+    assert_eq!((mv.x, mv.y), (0, 2));
+    assert!(engine.last_error().is_none());
+
+    engine.new_game();
+    engine.set_position(&Board::new_default());
+    let mv = engine.search(&SearchLimits::time(500)).best_move;
+    assert_eq!((mv.x, mv.y), (0, 0));
+
+    // A brain answering with a taken cell or not at all fails the move
+    let mut engine = ExternalEngine::new("stub", &format!("{} --occupied", STUB));
+    engine.set_position(&board);
+    engine.search(&SearchLimits::time(500));
+    assert_eq!(
+        engine.last_error(),
+        Some(&BrainError::Protocol("0,0".to_string()))
+    );
+    let mut engine = ExternalEngine::new("stub", &format!("{} --hang", STUB));
+    engine.set_position(&board);
+    assert!(!engine.search(&SearchLimits::time(100)).has_move());
+    assert!(matches!(engine.last_error(), Some(BrainError::Timeout(_))));
+
+    let mut engine = ExternalEngine::new("missing", "./no-such-brain");
+    engine.search(&SearchLimits::time(100));
+    assert!(matches!(engine.last_error(), Some(BrainError::Spawn(_))));
+}
+
+#[test]
+fn test_brain_output_is_protocol_only() {
+    // The opponent has four in a row, the brain has to block at 11,7
+    let mut input = String::from("START 15\nINFO timeout_turn 2000\nBOARD\n");
+    for x in 7..11 {
+        input += &format!("{},7,2\n", x);
+    }
+    input += "6,7,1\n8,8,1\n9,8,1\nDONE\nEND\n";
+    for algo in ["minimax", "hybrid_minimax"] {
+        let mut brain = Command::new(BRAIN)
+            .args(["--algo", algo, "--threads", "1"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        brain
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = brain.wait_with_output().unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, ["OK", "11,7"], "{}", algo);
+    }
+}
+
+#[test]
+fn test_external_engines_in_self_play() {
+    // The brain of this crate blocks a four through the protocol
+    let name = format!("{}{} --algo one_ply", EXTERNAL_PREFIX, BRAIN);
+    let mut engine = create_engine_with_external(&name, &EngineOptions::default()).unwrap();
+    assert_eq!(engine.name(), name);
+    let mut board = Board::new_default();
+    for col in 7..11 {
+        board.place(7, col, 1);
+    }
+    board.place(7, 6, 2);
+    board.place(8, 8, 2);
+    board.place(8, 9, 2);
+    engine.set_position(&board);
+    let mv = engine.search(&SearchLimits::time(500)).best_move;
+    assert_eq!((mv.x, mv.y), (7, 11));
+
+    let log = std::env::temp_dir().join(format!("pbrain-stub-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let stub = format!("{}{} --log {}", EXTERNAL_PREFIX, STUB, log.display());
+    let self_play = SelfPlay::new(20, false);
+    let record = self_play.play_game_with_opening(&stub, "one_ply", None, true);
+    assert_eq!(record.algo_player1, stub);
+    // The stub fills the first row and loses to one_ply
+    assert_eq!(record.winner, Some(2));
+    // One brain plays the whole game
+    let commands = std::fs::read_to_string(&log).unwrap();
+    let _ = std::fs::remove_file(&log);
+    let count = |command: &str| commands.lines().filter(|l| l.starts_with(command)).count();
+    assert_eq!(count("START"), 1);
+    let moves = record.states.iter().filter(|s| s.player == 1).count();
+    assert_eq!(count("BOARD"), moves);
+
+    // A brain which cannot answer loses instead of playing a stone at (0, 0)
+    let failing = format!("{}{} --occupied", EXTERNAL_PREFIX, STUB);
+    let record = self_play.play_game_with_opening("one_ply", &failing, None, true);
+    assert_eq!(record.winner, Some(1));
+    assert_eq!(record.total_steps, 1);
+}
@@ -0,0 +1,45 @@
+use serde_json::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const GOMOKU: &str = env!("CARGO_BIN_EXE_gomoku");
+
+#[test]
+fn test_json_rpc_output_is_json_only() {
+    // Player 1 has four in a row, minimax has to block at (7, 11)
+    let moves = [(7, 7), (7, 6), (7, 8), (8, 8), (7, 9), (8, 9), (7, 10)];
+    let mut input = String::new();
+    for (id, (row, col)) in moves.iter().enumerate() {
+        input += &format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"play\",\"params\":{{\"row\":{},\"col\":{}}}}}\n",
+            id, row, col
+        );
+    }
+    input += r#"{"jsonrpc":"2.0","id":9,"method":"best_move","params":{"options":{"depth":3,"threads":1}}}"#;
+    input += "\n";
+
+    let mut gomoku = Command::new(GOMOKU)
+        .args(["--jsonrpc", "-a", "minimax"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    gomoku
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = gomoku.wait_with_output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    let answers: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|_| panic!("not JSON: {}", line)))
+        .collect();
+    let best = answers.iter().find(|a| a["id"] == 9).unwrap();
+    assert_eq!(
+        (&best["result"]["row"], &best["result"]["col"]),
+        (&7.into(), &11.into())
+    );
+}
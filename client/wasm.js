@@ -12,9 +12,10 @@ wasmInit("./pkg/gomoku_bg.wasm").then(() => {
   console.log("WASM loaded successfully in main thread");
 });
 
-export function solve_with_api(input, algo_type, width, height) {
-  // Call our exported function
-  const result = gomoku_solve(input, algo_type, width, height);
+export function solve_with_api(input, algo_type, width, height, level) {
+  // Call our exported function, the options are a JSON encoded EngineOptions
+  const options = JSON.stringify({ level: level });
+  const result = gomoku_solve(input, algo_type, width, height, options);
 
   // Log the result to the console
   //console.log(result);
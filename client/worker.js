@@ -1,8 +1,8 @@
 import { solve_with_api } from './wasm.js'
 
 onmessage = function(input) {
-  // input.data expected [boardStr, algo_type, width, height]
-  var result = solve_with_api(input.data[0], input.data[1], input.data[2], input.data[3]);
+  // input.data expected [boardStr, algo_type, width, height, level]
+  var result = solve_with_api(input.data[0], input.data[1], input.data[2], input.data[3], input.data[4]);
   //console.log("result: " + result);
   postMessage(result);
 }
\ No newline at end of file